@@ -2,8 +2,7 @@ use criterion::{criterion_group, criterion_main, Bencher, Criterion};
 use httpencode::{HttpBuilder, Method, Uri, Version};
 
 fn build_req_long(b: &mut Bencher) {
-    let mut buf = Vec::new();
-    buf.reserve(1 << 14);
+    let mut buf = Vec::with_capacity(1 << 14);
 
     b.iter(|| -> Result<_, _> {
         buf.clear();
@@ -30,8 +29,7 @@ fn build_req_long(b: &mut Bencher) {
 }
 
 fn build_req_long_unsafe(b: &mut Bencher) {
-    let mut buf = Vec::new();
-    buf.reserve(1 << 14);
+    let mut buf = Vec::with_capacity(1 << 14);
 
     b.iter(|| -> Result<_, _> {
         unsafe {
@@ -60,8 +58,7 @@ fn build_req_long_unsafe(b: &mut Bencher) {
 }
 
 fn build_req_short(b: &mut Bencher) {
-    let mut buf = Vec::new();
-    buf.reserve(1 << 14);
+    let mut buf = Vec::with_capacity(1 << 14);
 
     b.iter(|| -> Result<_, _> {
         buf.clear();
@@ -76,8 +73,7 @@ fn build_req_short(b: &mut Bencher) {
 }
 
 fn build_req_short_unsafe(b: &mut Bencher) {
-    let mut buf = Vec::new();
-    buf.reserve(1 << 14);
+    let mut buf = Vec::with_capacity(1 << 14);
 
     b.iter(|| -> Result<_, _> {
         unsafe {
@@ -0,0 +1,484 @@
+//! Asynchronous writing of HTTP messages.
+//!
+//! [`HttpWriter`] stages a message in a fixed-size buffer in front of an
+//! asynchronous writer and flushes the buffer whenever it fills up, so
+//! messages of any size can be written using a bounded amount of memory.
+//!
+//! Writers and readers from tokio and futures-io can be used through the
+//! `Tokio` and `FuturesIo` adapters, enabled by the `tokio` and
+//! `futures-io` features respectively.
+
+use futures_core::Stream;
+
+use core::future::poll_fn;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::io;
+
+use crate::staging::Writer;
+pub use crate::staging::{PollRead, PollWrite};
+use crate::util::try_write;
+use crate::{HeaderValue, HttpBuilder, IoError, Method, SliceBuf, Status, Uri, Version};
+
+/// Adapter for tokio's `AsyncWrite` and `AsyncRead`.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct Tokio<T>(pub T);
+
+#[cfg(feature = "tokio")]
+impl<T: tokio::io::AsyncWrite + Unpin> PollWrite for Tokio<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: tokio::io::AsyncRead + Unpin> PollRead for Tokio<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = tokio::io::ReadBuf::new(buf);
+
+        Pin::new(&mut self.0)
+            .poll_read(cx, &mut buf)
+            .map_ok(|()| buf.filled().len())
+    }
+}
+
+/// Adapter for futures-io's `AsyncWrite` and `AsyncRead`.
+#[cfg(feature = "futures-io")]
+#[derive(Debug)]
+pub struct FuturesIo<T>(pub T);
+
+#[cfg(feature = "futures-io")]
+impl<T: futures_io::AsyncWrite + Unpin> PollWrite for FuturesIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<T: futures_io::AsyncRead + Unpin> PollRead for FuturesIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+/// Writer for HTTP messages on top of an asynchronous writer.
+///
+/// Each operation is written into a fixed-size staging buffer, which is
+/// flushed to the writer whenever an operation does not fit. Operations
+/// only fail with [`Error::OutOfBuffer`](crate::Error::OutOfBuffer) if
+/// they do not fit within an empty staging buffer. Errors from the writer
+/// are returned as [`IoError::Io`].
+///
+/// The message must be written in order: the start line, then headers,
+/// then [`finish`](Self::finish) followed by the body. Call
+/// [`flush`](Self::flush) once the message is complete.
+///
+/// # Cancel safety
+/// Staged bytes are only dropped from the staging buffer once the writer
+/// has accepted them. If the writer fails part way through a flush, or
+/// the future doing the flush is dropped, whatever the writer did not
+/// accept is written out by the next flush and nothing is written twice.
+///
+/// Writing the start line and headers, [`finish`](Self::finish) and
+/// [`flush`](Self::flush) are cancel safe: the operation has either been
+/// staged in full or not at all. The body methods are not, as body data
+/// that is written out directly or taken from a reader or stream is lost
+/// if they are cancelled part way through.
+#[derive(Debug)]
+pub struct HttpWriter<W> {
+    inner: Writer<W>,
+}
+
+impl<W: PollWrite + Unpin> HttpWriter<W> {
+    /// Create a writer with an 8KiB staging buffer.
+    pub fn new(io: W) -> Self {
+        Self::with_capacity(io, 8 * 1024)
+    }
+
+    /// Create a writer with a staging buffer of `capacity` bytes.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is less than 64 bytes.
+    pub fn with_capacity(io: W, capacity: usize) -> Self {
+        Self {
+            inner: Writer::new(io, capacity),
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Get the underlying writer, discarding anything that has not been
+    /// flushed yet.
+    pub fn into_inner(self) -> W {
+        self.inner.into_inner()
+    }
+
+    /// Write a request line.
+    pub async fn request(
+        &mut self,
+        method: Method<'_>,
+        version: Version<'_>,
+        uri: Uri<'_>,
+    ) -> Result<(), IoError> {
+        self.inner
+            .stage(|buf| HttpBuilder::request(buf, method, version, uri).map(drop))
+            .await
+    }
+
+    /// Write a status line.
+    pub async fn response(&mut self, version: Version<'_>, status: Status) -> Result<(), IoError> {
+        self.inner
+            .stage(|buf| HttpBuilder::response(buf, version, status).map(drop))
+            .await
+    }
+
+    /// Write a status line with a custom reason phrase.
+    pub async fn response_with_reason(
+        &mut self,
+        version: Version<'_>,
+        status: Status,
+        reason: &str,
+    ) -> Result<(), IoError> {
+        self.inner
+            .stage(|buf| HttpBuilder::response_with_reason(buf, version, status, reason).map(drop))
+            .await
+    }
+
+    /// Add a new header.
+    pub async fn header(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl HeaderValue,
+    ) -> Result<(), IoError> {
+        self.inner.header(key.as_ref(), &val).await
+    }
+
+    /// Add headers using the [`HttpBuilder`] API.
+    ///
+    /// Everything done by `f` is written atomically. It may be called a
+    /// second time, after flushing, if it runs out of space.
+    pub async fn headers<F>(&mut self, f: F) -> Result<(), IoError>
+    where
+        F: FnMut(&mut HttpBuilder<&mut SliceBuf<'_>>) -> crate::Result<()>,
+    {
+        self.inner.headers(f).await
+    }
+
+    /// Complete the header section.
+    pub async fn finish(&mut self) -> Result<(), IoError> {
+        self.inner.stage(|buf| try_write(buf, b"\r\n")).await
+    }
+
+    /// Write out body data. Data that does not fit in the staging buffer
+    /// is written out directly instead of being copied.
+    pub async fn body(&mut self, data: &[u8]) -> Result<(), IoError> {
+        self.inner.body(data).await
+    }
+
+    /// Copy the body from `reader` until it is exhausted, returning the
+    /// number of bytes copied.
+    pub async fn body_reader<R: PollRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<u64, IoError> {
+        self.inner.body_reader(reader).await
+    }
+
+    /// Write out each item of `stream` as body data, returning the number
+    /// of bytes written.
+    pub async fn body_stream<S, T>(&mut self, mut stream: S) -> Result<u64, IoError>
+    where
+        S: Stream<Item = T> + Unpin,
+        T: AsRef<[u8]>,
+    {
+        let mut total = 0;
+
+        while let Some(item) = next(&mut stream).await {
+            let data = item.as_ref();
+
+            self.inner.body(data).await?;
+            total += data.len() as u64;
+        }
+
+        Ok(total)
+    }
+
+    /// Copy the body from `reader` using the chunked transfer coding,
+    /// including the last chunk, returning the number of body bytes
+    /// copied.
+    ///
+    /// Each read from `reader` is written out as a single chunk.
+    pub async fn chunked_reader<R: PollRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<u64, IoError> {
+        let total = self.inner.chunked_reader(reader).await?;
+        self.inner.last_chunk().await?;
+
+        Ok(total)
+    }
+
+    /// Write out each item of `stream` as a chunk using the chunked
+    /// transfer coding, including the last chunk, returning the number of
+    /// body bytes written.
+    pub async fn chunked_stream<S, T>(&mut self, mut stream: S) -> Result<u64, IoError>
+    where
+        S: Stream<Item = T> + Unpin,
+        T: AsRef<[u8]>,
+    {
+        let mut total = 0;
+
+        while let Some(item) = next(&mut stream).await {
+            let data = item.as_ref();
+
+            self.inner.chunk(data).await?;
+            total += data.len() as u64;
+        }
+
+        self.inner.last_chunk().await?;
+
+        Ok(total)
+    }
+
+    /// Write out everything staged so far and flush the underlying
+    /// writer.
+    pub async fn flush(&mut self) -> Result<(), IoError> {
+        self.inner.flush().await
+    }
+}
+
+async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staging::block_on;
+
+    #[cfg(feature = "futures-io")]
+    struct Items<'a>(core::slice::Iter<'a, &'a str>);
+
+    #[cfg(feature = "futures-io")]
+    impl<'a> Stream for Items<'a> {
+        type Item = &'a str;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<&'a str>> {
+            Poll::Ready(self.0.next().copied())
+        }
+    }
+
+    fn expected_response(body: &str) -> Result<Vec<u8>, IoError> {
+        let mut expected = Vec::new();
+        let mut rsp = HttpBuilder::response(&mut expected, Version::Http11, Status::OK)?;
+        rsp.header("Content-Length", body.len())?;
+        rsp.body(&mut body.as_bytes())?;
+
+        Ok(expected)
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_adapter() -> Result<(), IoError> {
+        let body = "body ".repeat(40);
+
+        let mut writer = HttpWriter::with_capacity(Tokio(Vec::new()), 64);
+        block_on(async {
+            writer.response(Version::Http11, Status::OK).await?;
+            writer.header("Content-Length", body.len()).await?;
+            writer.finish().await?;
+            writer.body_reader(&mut Tokio(body.as_bytes())).await?;
+            writer.flush().await
+        })?;
+
+        assert_eq!(writer.get_ref().0, expected_response(&body)?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[test]
+    fn futures_io_adapter() -> Result<(), IoError> {
+        let mut writer = HttpWriter::with_capacity(FuturesIo(Vec::new()), 64);
+        block_on(async {
+            writer.response(Version::Http11, Status::OK).await?;
+            writer.header("Transfer-Encoding", "chunked").await?;
+            writer.finish().await?;
+            writer
+                .chunked_stream(Items(["hello", "", "world"].iter()))
+                .await?;
+            writer.flush().await
+        })?;
+
+        assert_eq!(
+            writer.get_ref().0,
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+               5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n"[..]
+        );
+
+        Ok(())
+    }
+
+    // Accepts at most 5 bytes per write and fails the `fail`th write.
+    #[cfg(feature = "tokio")]
+    struct Flaky {
+        out: Vec<u8>,
+        fail: usize,
+    }
+
+    #[cfg(feature = "tokio")]
+    impl tokio::io::AsyncWrite for Flaky {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.fail = self.fail.wrapping_sub(1);
+            if self.fail == 0 {
+                return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+            }
+
+            let len = buf.len().min(5);
+            self.out.extend_from_slice(&buf[..len]);
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn retries_after_partial_write() -> Result<(), IoError> {
+        let io = Flaky {
+            out: Vec::new(),
+            fail: 3,
+        };
+
+        let mut writer = HttpWriter::new(Tokio(io));
+        block_on(async {
+            writer.response(Version::Http11, Status::OK).await?;
+            writer.header("Content-Length", 4).await?;
+            writer.finish().await?;
+            writer.body(b"body").await
+        })?;
+
+        // The first two writes went through before the writer failed.
+        let err = block_on(writer.flush()).unwrap_err();
+        let source = std::error::Error::source(&err).unwrap();
+        let source = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(writer.get_ref().0.out, b"HTTP/1.1 2");
+
+        block_on(writer.flush())?;
+        assert_eq!(writer.get_ref().0.out, expected_response("body")?);
+
+        Ok(())
+    }
+
+    // Accepts at most 5 bytes per write and is pending once `ready`
+    // writes have been made.
+    #[cfg(feature = "futures-io")]
+    struct Stalls {
+        out: Vec<u8>,
+        ready: usize,
+    }
+
+    #[cfg(feature = "futures-io")]
+    impl futures_io::AsyncWrite for Stalls {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            if self.ready == 0 {
+                return Poll::Pending;
+            }
+            self.ready -= 1;
+
+            let len = buf.len().min(5);
+            self.out.extend_from_slice(&buf[..len]);
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[test]
+    fn flush_is_cancel_safe() -> Result<(), IoError> {
+        use std::future::Future;
+        use std::task::Waker;
+
+        let io = Stalls {
+            out: Vec::new(),
+            ready: 2,
+        };
+
+        let mut writer = HttpWriter::new(FuturesIo(io));
+        block_on(async {
+            writer.response(Version::Http11, Status::OK).await?;
+            writer.header("Content-Length", 4).await?;
+            writer.finish().await?;
+            writer.body(b"body").await
+        })?;
+
+        // Drop the flush once the writer stops accepting data.
+        {
+            let mut flush = Box::pin(writer.flush());
+            let mut cx = Context::from_waker(Waker::noop());
+            assert!(flush.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(writer.get_ref().0.out, b"HTTP/1.1 2");
+
+        writer.get_mut().0.ready = usize::MAX;
+        block_on(writer.flush())?;
+        assert_eq!(writer.get_ref().0.out, expected_response("body")?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use bytes::BufMut;
+
+// The standard base64 alphabet from RFC4648 section 4.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Calculate the length of `len` bytes once base64-encoded, including
+// padding.
+pub(crate) fn encoded_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+// Base64-encode the concatenation of `parts` into the buffer. The caller
+// is responsible for checking that there is enough space.
+pub(crate) fn write<B: BufMut>(buf: &mut B, parts: &[&[u8]]) {
+    // Pending bits, aligned to the least-significant bit. There are
+    // always fewer than 6 pending bits between bytes.
+    let mut bits = 0u32;
+    let mut len = 0;
+
+    for &b in parts.iter().flat_map(|part| part.iter()) {
+        bits = (bits << 8) | u32::from(b);
+        len += 8;
+
+        while len >= 6 {
+            len -= 6;
+            buf.put_u8(ALPHABET[((bits >> len) & 0x3F) as usize]);
+        }
+    }
+
+    if len != 0 {
+        // 2 pending bits are left over from a single trailing byte and 4
+        // from two trailing bytes, which are padded out to a full group
+        // with two and one `=` respectively.
+        buf.put_u8(ALPHABET[((bits << (6 - len)) & 0x3F) as usize]);
+        buf.put_slice(if len == 2 { b"==" } else { b"=" });
+    }
+}
@@ -0,0 +1,382 @@
+//! Blocking writing of HTTP messages.
+//!
+//! [`HttpWriter`] stages a message in a fixed-size buffer in front of an
+//! [`io::Write`] and flushes the buffer whenever it fills up, so messages
+//! of any size can be written using a bounded amount of memory.
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::io::{self, Read, Write};
+
+use crate::staging::{block_on, PollRead, PollWrite, Writer};
+use crate::util::try_write;
+use crate::{HeaderValue, HttpBuilder, IoError, Method, SliceBuf, Status, Uri, Version};
+
+/// Writer for HTTP messages on top of an [`io::Write`].
+///
+/// Each operation is written into a fixed-size staging buffer, which is
+/// flushed to the writer whenever an operation does not fit. Operations
+/// only fail with [`Error::OutOfBuffer`](crate::Error::OutOfBuffer) if
+/// they do not fit within an empty staging buffer. Errors from the writer
+/// are returned as [`IoError::Io`].
+///
+/// If the writer fails part way through a flush, whatever it did not
+/// accept stays staged and is written out by the next flush, so a failed
+/// operation can be retried without writing anything twice. Body data
+/// that is written out directly is not kept and can't be retried.
+///
+/// The message must be written in order: the start line, then headers,
+/// then [`finish`](Self::finish) followed by the body. Call
+/// [`flush`](Self::flush) once the message is complete.
+#[derive(Debug)]
+pub struct HttpWriter<W: Write> {
+    inner: Writer<Blocking<W>>,
+}
+
+impl<W: Write> HttpWriter<W> {
+    /// Create a writer with an 8KiB staging buffer.
+    pub fn new(io: W) -> Self {
+        Self::with_capacity(io, 8 * 1024)
+    }
+
+    /// Create a writer with a staging buffer of `capacity` bytes.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is less than 64 bytes.
+    pub fn with_capacity(io: W, capacity: usize) -> Self {
+        Self {
+            inner: Writer::new(Blocking(io), capacity),
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner.get_ref().0
+    }
+
+    /// Get a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner.get_mut().0
+    }
+
+    /// Get the underlying writer, discarding anything that has not been
+    /// flushed yet.
+    pub fn into_inner(self) -> W {
+        self.inner.into_inner().0
+    }
+
+    /// Write a request line.
+    pub fn request(
+        &mut self,
+        method: Method,
+        version: Version,
+        uri: Uri,
+    ) -> Result<&mut Self, IoError> {
+        block_on(
+            self.inner
+                .stage(|buf| HttpBuilder::request(buf, method, version, uri).map(drop)),
+        )?;
+
+        Ok(self)
+    }
+
+    /// Write a status line.
+    pub fn response(&mut self, version: Version, status: Status) -> Result<&mut Self, IoError> {
+        block_on(
+            self.inner
+                .stage(|buf| HttpBuilder::response(buf, version, status).map(drop)),
+        )?;
+
+        Ok(self)
+    }
+
+    /// Write a status line with a custom reason phrase.
+    pub fn response_with_reason(
+        &mut self,
+        version: Version,
+        status: Status,
+        reason: &str,
+    ) -> Result<&mut Self, IoError> {
+        block_on(self.inner.stage(|buf| {
+            HttpBuilder::response_with_reason(buf, version, status, reason).map(drop)
+        }))?;
+
+        Ok(self)
+    }
+
+    /// Add a new header.
+    pub fn header(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl HeaderValue,
+    ) -> Result<&mut Self, IoError> {
+        block_on(self.inner.header(key.as_ref(), &val))?;
+
+        Ok(self)
+    }
+
+    /// Add headers using the [`HttpBuilder`] API.
+    ///
+    /// Everything done by `f` is written atomically. It may be called a
+    /// second time, after flushing, if it runs out of space.
+    pub fn headers<F>(&mut self, f: F) -> Result<&mut Self, IoError>
+    where
+        F: FnMut(&mut HttpBuilder<&mut SliceBuf<'_>>) -> crate::Result<()>,
+    {
+        block_on(self.inner.headers(f))?;
+
+        Ok(self)
+    }
+
+    /// Complete the header section.
+    pub fn finish(&mut self) -> Result<&mut Self, IoError> {
+        block_on(self.inner.stage(|buf| try_write(buf, b"\r\n")))?;
+
+        Ok(self)
+    }
+
+    /// Write out body data. Data that does not fit in the staging buffer
+    /// is written out directly instead of being copied.
+    pub fn body(&mut self, data: &[u8]) -> Result<&mut Self, IoError> {
+        block_on(self.inner.body(data))?;
+
+        Ok(self)
+    }
+
+    /// Copy the body from `reader` until it is exhausted, returning the
+    /// number of bytes copied.
+    pub fn body_reader<R: Read + ?Sized>(&mut self, reader: &mut R) -> Result<u64, IoError> {
+        block_on(self.inner.body_reader(&mut Blocking(reader)))
+    }
+
+    /// Copy the body from `reader` using the chunked transfer coding,
+    /// including the last chunk, returning the number of body bytes
+    /// copied.
+    ///
+    /// Each read from `reader` is written out as a single chunk.
+    pub fn chunked_reader<R: Read + ?Sized>(&mut self, reader: &mut R) -> Result<u64, IoError> {
+        let total = block_on(self.inner.chunked_reader(&mut Blocking(reader)))?;
+        block_on(self.inner.last_chunk())?;
+
+        Ok(total)
+    }
+
+    /// Write out each item of `chunks` as a chunk using the chunked
+    /// transfer coding, including the last chunk, returning the number of
+    /// body bytes written.
+    pub fn chunked_iter<I, T>(&mut self, chunks: I) -> Result<u64, IoError>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let mut total = 0;
+
+        for item in chunks {
+            let data = item.as_ref();
+
+            block_on(self.inner.chunk(data))?;
+            total += data.len() as u64;
+        }
+
+        block_on(self.inner.last_chunk())?;
+
+        Ok(total)
+    }
+
+    /// Write out everything staged so far and flush the underlying
+    /// writer.
+    pub fn flush(&mut self) -> Result<(), IoError> {
+        block_on(self.inner.flush())
+    }
+}
+
+// Blocking I/O presented as `PollWrite` and `PollRead`, which are always
+// ready, so that the shared `Writer` can drive it with `block_on`.
+#[derive(Debug)]
+struct Blocking<T>(T);
+
+// The wrapped I/O object is never pinned.
+impl<T> Unpin for Blocking<T> {}
+
+impl<W: Write> PollWrite for Blocking<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(retry(|| self.0.write(buf)))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(retry(|| self.0.flush()))
+    }
+}
+
+impl<R: Read + ?Sized> PollRead for Blocking<&mut R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(retry(|| self.0.read(buf)))
+    }
+}
+
+// Run `f`, retrying if interrupted.
+fn retry<T>(mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match f() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staging::CHUNK_HEADER;
+    use crate::Error;
+
+    // Accepts at most 5 bytes per write.
+    #[derive(Default)]
+    struct Mock(Vec<u8>);
+
+    impl Write for Mock {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(5);
+            self.0.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flushes_instead_of_failing() -> Result<(), IoError> {
+        let agent = "example-agent/1.0 (with some details)";
+        let body = "body ".repeat(40);
+
+        let mut writer = HttpWriter::with_capacity(Mock::default(), 64);
+        writer
+            .request(Method::Post, Version::Http11, Uri::new(b"/upload"))?
+            .header("User-Agent", agent)?
+            .header("Content-Length", body.len())?
+            .finish()?;
+        writer.body_reader(&mut body.as_bytes())?;
+        writer.flush()?;
+
+        let mut expected = Vec::new();
+        let uri = Uri::new(b"/upload");
+        let mut req = HttpBuilder::request(&mut expected, Method::Post, Version::Http11, uri)?;
+        req.header("User-Agent", agent)?;
+        req.header("Content-Length", body.len())?;
+        req.body(&mut body.as_bytes())?;
+
+        assert_eq!(writer.get_ref().0, expected);
+
+        // A single header that can never fit is still an error.
+        let res = writer.header("X-Long", &*"x".repeat(64)).map(drop);
+        assert!(matches!(res, Err(IoError::Encode(Error::OutOfBuffer))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunked_bodies() -> Result<(), IoError> {
+        let body = "0123456789".repeat(6);
+
+        let mut writer = HttpWriter::with_capacity(Mock::default(), 64);
+        writer.chunked_reader(&mut body.as_bytes())?;
+        writer.chunked_iter(["hello", "", "world"])?;
+        writer.flush()?;
+
+        // Each chunk read from the reader fills the staging buffer, apart
+        // from the room left for the chunk framing.
+        let first = 64 - CHUNK_HEADER - 2;
+        let expected = format!(
+            "{:X}\r\n{}\r\n{:X}\r\n{}\r\n0\r\n\r\n5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n",
+            first,
+            &body[..first],
+            body.len() - first,
+            &body[first..]
+        );
+        assert_eq!(writer.get_ref().0, expected.as_bytes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn surfaces_io_errors() {
+        struct Broken;
+
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = HttpWriter::new(Broken);
+        writer.response(Version::Http11, Status::OK).unwrap();
+
+        match writer.flush() {
+            Err(IoError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::BrokenPipe),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn retries_after_failed_flush() -> Result<(), IoError> {
+        // Accepts at most 5 bytes per write and fails the third write.
+        struct Flaky {
+            out: Vec<u8>,
+            writes: usize,
+        }
+
+        impl Write for Flaky {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.writes += 1;
+                if self.writes == 3 {
+                    return Err(io::ErrorKind::ConnectionReset.into());
+                }
+
+                let len = buf.len().min(5);
+                self.out.extend_from_slice(&buf[..len]);
+                Ok(len)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let io = Flaky {
+            out: Vec::new(),
+            writes: 0,
+        };
+
+        let mut writer = HttpWriter::new(io);
+        writer
+            .response(Version::Http11, Status::OK)?
+            .header("Content-Length", 4)?
+            .finish()?
+            .body(b"body")?;
+
+        assert!(matches!(writer.flush(), Err(IoError::Io(_))));
+        assert_eq!(writer.get_ref().out, b"HTTP/1.1 2");
+
+        writer.flush()?;
+        assert_eq!(
+            writer.get_ref().out,
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nbody"[..]
+        );
+
+        Ok(())
+    }
+}
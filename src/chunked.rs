@@ -0,0 +1,339 @@
+use arrayvec::ArrayVec;
+use bytes::{Buf, BufMut};
+
+use crate::util::{
+    hex_len, is_tchar, put_buf, quoted_string_len, validate_token, write_hex, write_quoted_string,
+    Sink,
+};
+use crate::{Error, HeaderValue, HttpBuilder, Result};
+
+/// Writer for a message body using the chunked transfer coding.
+///
+/// This is created by [`HttpBuilder::chunked`](crate::HttpBuilder::chunked)
+/// once the header block has been completed.
+#[derive(Debug)]
+pub struct ChunkedWriter<B: BufMut> {
+    buf: B,
+    declared: Declared,
+}
+
+impl<B: BufMut> ChunkedWriter<B> {
+    pub(crate) fn new(buf: B, declared: Declared) -> Self {
+        Self { buf, declared }
+    }
+
+    /// Write the remaining contents of `data` out as a single chunk.
+    ///
+    /// An empty chunk would terminate the body so writing one does
+    /// nothing. Use [`finish`](Self::finish) to write the last chunk.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    #[inline]
+    pub fn write_chunk<I: Buf>(&mut self, data: &mut I) -> Result<&mut Self> {
+        self.write_chunk_with_ext(&[], data)
+    }
+
+    /// Write the remaining contents of `data` out as a single chunk with
+    /// the provided chunk extensions.
+    ///
+    /// Each extension is a name and an optional value. Values that are
+    /// not tokens are written out as a quoted-string.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn write_chunk_with_ext<I: Buf>(
+        &mut self,
+        ext: &[(&str, Option<&str>)],
+        data: &mut I,
+    ) -> Result<&mut Self> {
+        let len = data.remaining();
+        if len == 0 {
+            return Ok(self);
+        }
+
+        let required = hex_len(len) + chunk_ext_len(ext)? + len + b"\r\n\r\n".len();
+        if self.buf.remaining_mut() < required {
+            return Err(Error::OutOfBuffer);
+        }
+
+        write_hex(&mut self.buf, len);
+        write_chunk_ext(&mut self.buf, ext);
+        self.buf.put_slice(b"\r\n");
+        put_buf(&mut self.buf, data);
+        self.buf.put_slice(b"\r\n");
+
+        Ok(self)
+    }
+
+    /// Write the last chunk, completing the body, and return the
+    /// underlying buffer.
+    #[inline]
+    pub fn finish(mut self) -> Result<B> {
+        const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+
+        if self.buf.remaining_mut() < LAST_CHUNK.len() {
+            return Err(Error::OutOfBuffer);
+        }
+
+        self.buf.put_slice(LAST_CHUNK);
+
+        Ok(self.buf)
+    }
+
+    /// Write the last chunk and start writing the trailer section.
+    ///
+    /// If the header section contained a `Trailer` header then only the
+    /// fields that it declared can be written.
+    #[inline]
+    pub fn trailers(mut self) -> Result<TrailerWriter<B>> {
+        const LAST_CHUNK: &[u8] = b"0\r\n";
+
+        if self.buf.remaining_mut() < LAST_CHUNK.len() {
+            return Err(Error::OutOfBuffer);
+        }
+
+        self.buf.put_slice(LAST_CHUNK);
+
+        Ok(TrailerWriter {
+            // Safety: The trailer section has the same syntax as the
+            //         header section.
+            builder: unsafe { HttpBuilder::from_buf(self.buf) },
+            declared: self.declared,
+        })
+    }
+
+    /// Get the underlying buffer without completing the body.
+    pub fn into_buf(self) -> B {
+        self.buf
+    }
+
+    /// Get the number of remaining bytes within the underlying buffer.
+    pub fn remaining(&self) -> usize {
+        self.buf.remaining_mut()
+    }
+}
+
+/// Writer for the trailer section following a chunked message body.
+///
+/// This is created by [`ChunkedWriter::trailers`].
+#[derive(Debug)]
+pub struct TrailerWriter<B: BufMut> {
+    builder: HttpBuilder<B>,
+    declared: Declared,
+}
+
+impl<B: BufMut> TrailerWriter<B> {
+    /// Add a new trailer field.
+    ///
+    /// This will fail if the field is one that is not allowed within
+    /// a trailer or, if a `Trailer` header was written, if it was not
+    /// one of the declared fields.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    #[inline]
+    pub fn trailer(&mut self, key: impl AsRef<[u8]>, val: impl HeaderValue) -> Result<&mut Self> {
+        let key = key.as_ref();
+
+        if is_forbidden_trailer(key) {
+            return Err(Error::ForbiddenTrailer);
+        }
+        if !self.declared.allows(key) {
+            return Err(Error::UndeclaredTrailer);
+        }
+
+        self.builder.header(key, val)?;
+
+        Ok(self)
+    }
+
+    /// Complete the trailer section and return the underlying buffer.
+    #[inline]
+    pub fn finish(self) -> Result<B> {
+        self.builder.finish()
+    }
+
+    /// Get the underlying buffer without completing the trailer section.
+    pub fn into_buf(self) -> B {
+        self.builder.into_buf()
+    }
+
+    /// Get the number of remaining bytes within the underlying buffer.
+    pub fn remaining(&self) -> usize {
+        self.builder.remaining()
+    }
+}
+
+// The space for the field names declared by the `Trailer` headers of a
+// single message. Past this no more names are recorded and any trailer
+// field is allowed.
+const DECLARED_TRAILERS_LEN: usize = 128;
+
+// The field names declared by the `Trailer` headers written through an
+// `HttpBuilder`, as defined by RFC7230.
+//
+// >     Trailer = 1#field-name
+//
+// Names are copied into a fixed buffer, each followed by a comma, so that
+// the builder doesn't need to borrow or allocate a copy of the header
+// value.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Declared {
+    names: ArrayVec<[u8; DECLARED_TRAILERS_LEN]>,
+    overflowed: bool,
+}
+
+impl Declared {
+    // Record the field names within a `Trailer` header value. This is
+    // done on a copy so that nothing is recorded if writing the header
+    // fails.
+    pub(crate) fn declare<V: HeaderValue>(&self, val: &V) -> Self {
+        let mut declared = self.clone();
+        let mut in_name = false;
+
+        let mut sink = Sink::new(|bytes: &[u8]| {
+            for &c in bytes {
+                if is_tchar(c) {
+                    declared.push(c);
+                    in_name = true;
+                } else if in_name {
+                    declared.push(b',');
+                    in_name = false;
+                }
+            }
+        });
+        let _ = val.put(&mut sink);
+
+        if in_name {
+            declared.push(b',');
+        }
+
+        declared
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.overflowed |= self.names.try_push(byte).is_err();
+    }
+
+    // Whether a trailer field can be sent. Any field can be sent if no
+    // `Trailer` header was written or if too many names were declared to
+    // keep track of.
+    fn allows(&self, name: &[u8]) -> bool {
+        self.names.is_empty()
+            || self.overflowed
+            || self
+                .names
+                .split(|&c| c == b',')
+                .any(|declared| !declared.is_empty() && declared.eq_ignore_ascii_case(name))
+    }
+}
+
+// Fields that must not be sent within a trailer, as listed in
+// RFC7230 section 4.1.2.
+//
+// > A sender MUST NOT generate a trailer that contains a field necessary
+// > for message framing (e.g., Transfer-Encoding and Content-Length),
+// > routing (e.g., Host), request modifiers (e.g., controls and
+// > conditionals in Section 5 of [RFC7231]), authentication (e.g., see
+// > [RFC7235] and [RFC6265]), response control data (e.g., see Section
+// > 7.1 of [RFC7231]), or determining how to process the payload (e.g.,
+// > Content-Encoding, Content-Type, Content-Range, and Trailer).
+fn is_forbidden_trailer(name: &[u8]) -> bool {
+    const FORBIDDEN: &[&[u8]] = &[
+        // Message framing
+        b"Transfer-Encoding",
+        b"Content-Length",
+        // Routing
+        b"Host",
+        // Request modifiers
+        b"Cache-Control",
+        b"Expect",
+        b"Max-Forwards",
+        b"Pragma",
+        b"Range",
+        b"TE",
+        b"If-Match",
+        b"If-None-Match",
+        b"If-Modified-Since",
+        b"If-Unmodified-Since",
+        b"If-Range",
+        // Authentication
+        b"Authorization",
+        b"Proxy-Authorization",
+        b"WWW-Authenticate",
+        b"Proxy-Authenticate",
+        b"Cookie",
+        b"Set-Cookie",
+        // Response control data
+        b"Age",
+        b"Date",
+        b"Expires",
+        b"Location",
+        b"Retry-After",
+        b"Vary",
+        b"Warning",
+        // Payload processing
+        b"Content-Encoding",
+        b"Content-Type",
+        b"Content-Range",
+        b"Trailer",
+    ];
+
+    FORBIDDEN
+        .iter()
+        .any(|forbidden| forbidden.eq_ignore_ascii_case(name))
+}
+
+// Validate a list of chunk extensions as defined by RFC7230 and
+// calculate their length once written out.
+//
+// >     chunk-ext      = *( ";" chunk-ext-name [ "=" chunk-ext-val ] )
+// >
+// >     chunk-ext-name = token
+// >     chunk-ext-val  = token / quoted-string
+fn chunk_ext_len(ext: &[(&str, Option<&str>)]) -> Result<usize> {
+    let mut len = 0;
+
+    for &(name, value) in ext {
+        if !validate_token(name.as_bytes()) {
+            return Err(Error::InvalidChunkExtension);
+        }
+
+        len += b";".len() + name.len();
+
+        if let Some(value) = value {
+            let value = value.as_bytes();
+
+            len += b"=".len();
+            len += if validate_token(value) {
+                value.len()
+            } else {
+                quoted_string_len(value).ok_or(Error::InvalidChunkExtension)?
+            };
+        }
+    }
+
+    Ok(len)
+}
+
+fn write_chunk_ext<B: BufMut>(buf: &mut B, ext: &[(&str, Option<&str>)]) {
+    for &(name, value) in ext {
+        buf.put_u8(b';');
+        buf.put_slice(name.as_bytes());
+
+        if let Some(value) = value {
+            let value = value.as_bytes();
+
+            buf.put_u8(b'=');
+            if validate_token(value) {
+                buf.put_slice(value);
+            } else {
+                write_quoted_string(buf, value);
+            }
+        }
+    }
+}
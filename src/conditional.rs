@@ -0,0 +1,457 @@
+//! Evaluation of conditional requests as defined by RFC 7232.
+//!
+//! [`Preconditions`] holds the validators sent with a request, either
+//! provided directly or read from the parsed request headers. These are
+//! evaluated against the [`Validators`] of the current representation
+//! following the precedence in RFC 7232 section 6:
+//!
+//! 1. `If-Match`, or `If-Unmodified-Since` when there is no `If-Match`,
+//!    fails the request with `412 Precondition Failed`.
+//! 2. `If-None-Match`, or `If-Modified-Since` for `GET` and `HEAD`
+//!    requests when there is no `If-None-Match`, results in a
+//!    `304 Not Modified` for `GET` and `HEAD` requests and a
+//!    `412 Precondition Failed` otherwise.
+//!
+//! `If-Range` only affects whether a `Range` header is honoured and is
+//! not evaluated here.
+//!
+//! ```
+//! # use httpencode::*;
+//! # use httpencode::conditional::{Conditional, Preconditions, Validators};
+//! # use httpencode::headers::{EntityTag, EntityTags};
+//! # fn main() -> Result<(), Error> {
+//! let tags = [EntityTag::strong("v2")];
+//! let preconditions = Preconditions::new().if_none_match(EntityTags::List(&tags));
+//! let current = Validators::new().etag(EntityTag::strong("v2"));
+//!
+//! let mut buf = Vec::new();
+//! match preconditions.respond(&mut buf, Method::Get, &current)? {
+//!     Conditional::NotModified(mut res) => {
+//!         res.header("ETag", EntityTag::strong("v2"))?
+//!             .header("Content-Length", 1024u64)?;
+//!         res.finish()?;
+//!     }
+//!     _ => unreachable!(),
+//! }
+//!
+//! assert_eq!(buf, &b"HTTP/1.1 304 Not Modified\r\nETag: \"v2\"\r\n\r\n"[..]);
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::BufMut;
+#[cfg(feature = "httpdate")]
+use core::str;
+
+use crate::decode::Header;
+use crate::headers::{EntityTag, EntityTags};
+use crate::util::validate_etagc;
+#[cfg(feature = "httpdate")]
+use crate::HttpDate;
+use crate::{response, Error, HeaderValue, HttpBuilder, Method, Result};
+
+/// The validators of the selected representation of the target resource.
+#[derive(Copy, Clone, Debug)]
+pub struct Validators<'a> {
+    exists: bool,
+    etag: Option<EntityTag<'a>>,
+    #[cfg(feature = "httpdate")]
+    last_modified: Option<HttpDate>,
+}
+
+impl<'a> Validators<'a> {
+    /// Validators for a representation that exists but has no entity tag
+    /// or modification date yet.
+    pub fn new() -> Self {
+        Self {
+            exists: true,
+            etag: None,
+            #[cfg(feature = "httpdate")]
+            last_modified: None,
+        }
+    }
+
+    /// The target resource has no current representation.
+    ///
+    /// `If-Match: *` fails and `If-None-Match: *` passes against these
+    /// validators.
+    pub fn missing() -> Self {
+        Self {
+            exists: false,
+            ..Self::new()
+        }
+    }
+
+    /// Set the entity tag of the representation.
+    pub fn etag(mut self, etag: EntityTag<'a>) -> Self {
+        self.etag = Some(etag);
+        self
+    }
+
+    /// Set the last modification date of the representation.
+    #[cfg(feature = "httpdate")]
+    pub fn last_modified(mut self, last_modified: HttpDate) -> Self {
+        self.last_modified = Some(last_modified);
+        self
+    }
+}
+
+impl Default for Validators<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An entity-tag list provided directly or still within the request
+/// headers.
+#[derive(Copy, Clone, Debug)]
+enum Tags<'a> {
+    Typed(EntityTags<'a>),
+    // All the fields with the given name are combined into one list.
+    Fields(&'a [Header<'a>], &'static str),
+}
+
+impl Tags<'_> {
+    // Whether any of the tags match the current representation. `*`
+    // matches if there is a current representation at all.
+    fn matches(&self, current: &Validators, strong: bool) -> bool {
+        let etag = match current.etag {
+            Some(etag) => etag,
+            None => return current.exists && self.is_any(),
+        };
+        let compare = |weak: bool, tag: &[u8]| {
+            tag == etag.tag().as_bytes() && (!strong || (!weak && !etag.is_weak()))
+        };
+
+        match *self {
+            Tags::Typed(EntityTags::Any) => current.exists,
+            Tags::Typed(EntityTags::List(tags)) => tags
+                .iter()
+                .any(|tag| compare(tag.is_weak(), tag.tag().as_bytes())),
+            Tags::Fields(headers, name) => fields(headers, name).any(|value| {
+                (value == b"*" && current.exists)
+                    || parse_tags(value, |weak, tag| compare(weak, tag)) == Some(true)
+            }),
+        }
+    }
+
+    fn is_any(&self) -> bool {
+        match *self {
+            Tags::Typed(tags) => tags == EntityTags::Any,
+            Tags::Fields(headers, name) => fields(headers, name).any(|value| value == b"*"),
+        }
+    }
+}
+
+/// The outcome of evaluating the preconditions of a request.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// All preconditions passed, continue with the full response.
+    Continue,
+    /// The representation has not been modified, respond with
+    /// `304 Not Modified`.
+    NotModified,
+    /// A precondition failed, respond with `412 Precondition Failed`.
+    PreconditionFailed,
+}
+
+/// The validators sent with a request.
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Preconditions<'a> {
+    if_match: Option<Tags<'a>>,
+    if_none_match: Option<Tags<'a>>,
+    #[cfg(feature = "httpdate")]
+    if_modified_since: Option<HttpDate>,
+    #[cfg(feature = "httpdate")]
+    if_unmodified_since: Option<HttpDate>,
+}
+
+impl<'a> Preconditions<'a> {
+    /// Create an empty set of preconditions, which always evaluates to
+    /// [`Outcome::Continue`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the preconditions from parsed request headers.
+    ///
+    /// Fails with [`Error::InvalidHeaderValue`] if an `If-Match` or
+    /// `If-None-Match` field is not a valid entity-tag list. Dates that
+    /// can't be parsed are ignored, as required by RFC 7232.
+    pub fn from_headers(headers: &'a [Header<'a>]) -> Result<Self> {
+        let mut preconditions = Self::new();
+
+        for header in headers {
+            let (tags, name) = if header.name.eq_ignore_ascii_case(b"if-match") {
+                (&mut preconditions.if_match, "if-match")
+            } else if header.name.eq_ignore_ascii_case(b"if-none-match") {
+                (&mut preconditions.if_none_match, "if-none-match")
+            } else {
+                #[cfg(feature = "httpdate")]
+                preconditions.read_date(header);
+                continue;
+            };
+
+            if header.value != b"*" && parse_tags(header.value, |_, _| false).is_none() {
+                return Err(Error::InvalidHeaderValue);
+            }
+
+            *tags = Some(Tags::Fields(headers, name));
+        }
+
+        Ok(preconditions)
+    }
+
+    #[cfg(feature = "httpdate")]
+    fn read_date(&mut self, header: &Header) {
+        let date = if header.name.eq_ignore_ascii_case(b"if-modified-since") {
+            &mut self.if_modified_since
+        } else if header.name.eq_ignore_ascii_case(b"if-unmodified-since") {
+            &mut self.if_unmodified_since
+        } else {
+            return;
+        };
+
+        // Only the first field is used if the header is repeated.
+        if date.is_none() {
+            *date = str::from_utf8(header.value)
+                .ok()
+                .and_then(|value| value.parse().ok());
+        }
+    }
+
+    /// Set the `If-Match` precondition.
+    pub fn if_match(mut self, tags: EntityTags<'a>) -> Self {
+        self.if_match = Some(Tags::Typed(tags));
+        self
+    }
+
+    /// Set the `If-None-Match` precondition.
+    pub fn if_none_match(mut self, tags: EntityTags<'a>) -> Self {
+        self.if_none_match = Some(Tags::Typed(tags));
+        self
+    }
+
+    /// Set the `If-Modified-Since` precondition.
+    #[cfg(feature = "httpdate")]
+    pub fn if_modified_since(mut self, date: HttpDate) -> Self {
+        self.if_modified_since = Some(date);
+        self
+    }
+
+    /// Set the `If-Unmodified-Since` precondition.
+    #[cfg(feature = "httpdate")]
+    pub fn if_unmodified_since(mut self, date: HttpDate) -> Self {
+        self.if_unmodified_since = Some(date);
+        self
+    }
+
+    /// Evaluate the preconditions for a request with the given method
+    /// against the current representation.
+    pub fn evaluate(&self, method: Method, current: &Validators) -> Outcome {
+        let safe = matches!(method, Method::Get | Method::Head);
+
+        if let Some(tags) = &self.if_match {
+            if !tags.matches(current, true) {
+                return Outcome::PreconditionFailed;
+            }
+        } else if !self.unmodified_since(current) {
+            return Outcome::PreconditionFailed;
+        }
+
+        if let Some(tags) = &self.if_none_match {
+            if tags.matches(current, false) {
+                return if safe {
+                    Outcome::NotModified
+                } else {
+                    Outcome::PreconditionFailed
+                };
+            }
+        } else if safe && !self.modified_since(current) {
+            return Outcome::NotModified;
+        }
+
+        Outcome::Continue
+    }
+
+    #[cfg(feature = "httpdate")]
+    fn unmodified_since(&self, current: &Validators) -> bool {
+        match (self.if_unmodified_since, current.last_modified) {
+            (Some(date), Some(last_modified)) => last_modified <= date,
+            _ => true,
+        }
+    }
+
+    #[cfg(not(feature = "httpdate"))]
+    fn unmodified_since(&self, _: &Validators) -> bool {
+        true
+    }
+
+    #[cfg(feature = "httpdate")]
+    fn modified_since(&self, current: &Validators) -> bool {
+        match (self.if_modified_since, current.last_modified) {
+            (Some(date), Some(last_modified)) => last_modified > date,
+            _ => true,
+        }
+    }
+
+    #[cfg(not(feature = "httpdate"))]
+    fn modified_since(&self, _: &Validators) -> bool {
+        true
+    }
+
+    /// Evaluate the preconditions and start the matching response.
+    ///
+    /// If a precondition results in a `304` or `412` response then its
+    /// status line is written to `buf`, otherwise `buf` is handed back
+    /// untouched so the full response can be written.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn respond<B: BufMut>(
+        &self,
+        buf: B,
+        method: Method,
+        current: &Validators,
+    ) -> Result<Conditional<B>> {
+        Ok(match self.evaluate(method, current) {
+            Outcome::Continue => Conditional::Continue(buf),
+            Outcome::NotModified => Conditional::NotModified(NotModified {
+                builder: response::not_modified(buf)?,
+                last_modified: current.etag.is_none(),
+            }),
+            Outcome::PreconditionFailed => {
+                Conditional::PreconditionFailed(response::precondition_failed(buf)?)
+            }
+        })
+    }
+}
+
+/// The response started by [`Preconditions::respond`].
+#[derive(Debug)]
+pub enum Conditional<B: BufMut> {
+    /// All preconditions passed, the full response should be written to
+    /// the buffer.
+    Continue(B),
+    /// A `304 Not Modified` response.
+    NotModified(NotModified<B>),
+    /// A `412 Precondition Failed` response.
+    PreconditionFailed(HttpBuilder<B>),
+}
+
+/// Builder for a `304 Not Modified` response.
+///
+/// The headers of the full `200 OK` response can be passed to
+/// [`header`](Self::header) unchanged. Only the headers listed in
+/// RFC 7232 section 4.1 are written out, along with `Last-Modified` when
+/// the representation has no entity tag. All other headers, including
+/// `Content-Length` and `Transfer-Encoding`, are dropped.
+#[derive(Debug)]
+pub struct NotModified<B: BufMut> {
+    builder: HttpBuilder<B>,
+    last_modified: bool,
+}
+
+impl<B: BufMut> NotModified<B> {
+    /// Write a header if it is allowed within a `304` response, otherwise
+    /// silently drop it.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn header(&mut self, key: impl AsRef<[u8]>, val: impl HeaderValue) -> Result<&mut Self> {
+        const KEPT: &[&[u8]] = &[
+            b"cache-control",
+            b"content-location",
+            b"date",
+            b"etag",
+            b"expires",
+            b"vary",
+        ];
+
+        let key = key.as_ref();
+        let kept = KEPT.iter().any(|kept| kept.eq_ignore_ascii_case(key))
+            || (self.last_modified && key.eq_ignore_ascii_case(b"last-modified"));
+
+        if kept {
+            self.builder.header(key, val)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Finish the response. A `304` response never has a body.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn finish(self) -> Result<B> {
+        self.builder.finish()
+    }
+
+    /// Get the underlying buffer.
+    pub fn into_buf(self) -> B {
+        self.builder.into_buf()
+    }
+
+    /// The remaining space within the buffer.
+    pub fn remaining(&self) -> usize {
+        self.builder.remaining()
+    }
+}
+
+// Iterate over the values of all the header fields with the given name.
+fn fields<'h>(headers: &'h [Header<'h>], name: &'h str) -> impl Iterator<Item = &'h [u8]> {
+    headers
+        .iter()
+        .filter(move |header| header.name.eq_ignore_ascii_case(name.as_bytes()))
+        .map(|header| header.value)
+}
+
+// Parse an entity-tag list as defined by RFC7232, calling `f` with
+// whether each tag is weak and its opaque tag. Returns whether `f`
+// returned true for any tag, or `None` if `value` is not a valid list.
+//
+// >     If-Match       = "*" / 1#entity-tag
+// >     entity-tag     = [ weak ] opaque-tag
+// >     weak           = %x57.2F ; "W/", case-sensitive
+fn parse_tags(value: &[u8], mut f: impl FnMut(bool, &[u8]) -> bool) -> Option<bool> {
+    let is_separator = |c: &u8| matches!(c, b',' | b' ' | b'\t');
+    let mut rest = value;
+    let mut found = None;
+
+    loop {
+        // Empty list elements are allowed, a list with no tags at all is
+        // not.
+        let start = match rest.iter().position(|c| !is_separator(c)) {
+            Some(start) => start,
+            None => return found,
+        };
+        let (weak, tag) = match &rest[start..] {
+            [b'W', b'/', b'"', tag @ ..] => (true, tag),
+            [b'"', tag @ ..] => (false, tag),
+            _ => return None,
+        };
+
+        let end = tag.iter().position(|&c| c == b'"')?;
+        if !validate_etagc(&tag[..end]) {
+            return None;
+        }
+
+        let matched = f(weak, &tag[..end]);
+        found = Some(found == Some(true) || matched);
+
+        // Elements must be separated by a comma, possibly surrounded by
+        // whitespace.
+        rest = &tag[end + 1..];
+        let trimmed = rest.iter().position(|&c| c != b' ' && c != b'\t');
+        match trimmed.map(|i| rest[i]) {
+            None => return found,
+            Some(b',') => (),
+            Some(_) => return None,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use bytes::BufMut;
+
+use core::fmt;
+use core::mem::MaybeUninit;
+
+use crate::util::{Consume, Sink};
+use crate::Rewind;
+
+/// A buffer which counts the bytes written to it without storing them.
+///
+/// This can be used with [`HttpBuilder`](crate::HttpBuilder) to measure
+/// the exact size of a message before writing it out for real.
+///
+/// ```
+/// # use httpencode::*;
+/// let uri = Uri::new(b"/");
+/// let mut req = HttpBuilder::request(Counter::new(), Method::Get, Version::Http11, uri)?;
+/// req.header("Host", "example.com")?;
+///
+/// assert_eq!(req.finish()?.count(), 37);
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Clone)]
+pub struct Counter {
+    sink: Sink<Count>,
+}
+
+// Counts the bytes passed on to it by a `Sink`.
+#[derive(Clone)]
+struct Count(usize);
+
+impl Consume for Count {
+    fn consume(&mut self, bytes: &[u8]) {
+        self.0 += bytes.len();
+    }
+}
+
+impl Counter {
+    /// Create a new counter starting at zero.
+    pub fn new() -> Self {
+        Self {
+            sink: Sink::new(Count(0)),
+        }
+    }
+
+    /// The number of bytes written so far.
+    pub fn count(&self) -> usize {
+        self.sink.get_ref().0
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Counter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Counter")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+impl BufMut for Counter {
+    fn remaining_mut(&self) -> usize {
+        usize::MAX - self.count()
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        self.sink.advance_mut(cnt);
+    }
+
+    fn bytes_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        self.sink.bytes_mut()
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.sink.put_slice(src);
+    }
+}
+
+impl Rewind for Counter {
+    fn checkpoint(&self) -> usize {
+        self.count()
+    }
+
+    fn rewind(&mut self, checkpoint: usize) {
+        let count = &mut self.sink.get_mut().0;
+        *count = (*count).min(checkpoint);
+    }
+}
@@ -0,0 +1,240 @@
+//! Zero-allocation parsing of HTTP/1.x messages.
+//!
+//! The parsers here accept the same syntax that [`HttpBuilder`] produces
+//! and validate it using the same rules, so anything the builder would
+//! refuse to write is rejected. Anything written by the builder is read
+//! back exactly, except that header values lose any leading or trailing
+//! whitespace.
+//!
+//! [`HttpBuilder`]: crate::HttpBuilder
+
+use core::str;
+
+use memchr::memchr;
+
+use crate::util::{
+    validate_escaped_uri, validate_header_field, validate_header_name, validate_method,
+    validate_reason, validate_version, CONNECT, DELETE, GET, HEAD, HTTP_1_0, HTTP_1_1, OPTIONS,
+    PATCH, POST, PUT, TRACE,
+};
+use crate::{Error, Method, Result, Status, Uri, Version};
+
+/// A header field borrowed from the parsed buffer.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Header<'b> {
+    pub name: &'b [u8],
+    pub value: &'b [u8],
+}
+
+/// A parsed request line.
+#[derive(Copy, Clone, Debug)]
+pub struct RequestLine<'b> {
+    pub method: Method<'b>,
+    pub uri: Uri<'b>,
+    pub version: Version<'b>,
+}
+
+/// A parsed status line.
+#[derive(Copy, Clone, Debug)]
+pub struct StatusLine<'b> {
+    pub version: Version<'b>,
+    pub status: Status,
+    pub reason: &'b str,
+}
+
+impl Header<'_> {
+    /// A header with an empty name and value, useful for initializing
+    /// the slice passed to [`headers`].
+    pub const EMPTY: Header<'static> = Header {
+        name: b"",
+        value: b"",
+    };
+}
+
+/// Parse a request line from the start of `buf`.
+///
+/// Returns `None` if `buf` does not contain a complete line yet,
+/// otherwise returns the request line along with the number of bytes
+/// that it took up.
+pub fn request_line(buf: &[u8]) -> Result<Option<(RequestLine<'_>, usize)>> {
+    let (line, len) = match next_line(buf)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+
+    let mut parts = line.splitn(3, |&c| c == b' ');
+    let (method, uri, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(uri), Some(version)) => (method, uri, version),
+        _ => return Err(Error::InvalidStartLine),
+    };
+
+    let line = RequestLine {
+        method: parse_method(method)?,
+        uri: parse_uri(uri)?,
+        version: parse_version(version)?,
+    };
+
+    Ok(Some((line, len)))
+}
+
+/// Parse a status line from the start of `buf`.
+///
+/// Returns `None` if `buf` does not contain a complete line yet,
+/// otherwise returns the status line along with the number of bytes
+/// that it took up.
+pub fn status_line(buf: &[u8]) -> Result<Option<(StatusLine<'_>, usize)>> {
+    let (line, len) = match next_line(buf)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+
+    let mut parts = line.splitn(3, |&c| c == b' ');
+    let (version, status, reason) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(version), Some(status), Some(reason)) => (version, status, reason),
+        _ => return Err(Error::InvalidStartLine),
+    };
+
+    let line = StatusLine {
+        version: parse_version(version)?,
+        status: parse_status(status)?,
+        reason: parse_reason(reason)?,
+    };
+
+    Ok(Some((line, len)))
+}
+
+/// Parse a header block, including the empty line that terminates it,
+/// from the start of `buf`.
+///
+/// Parsed headers are stored in `headers`, if there are more headers
+/// than fit then this fails with [`Error::TooManyHeaders`].
+///
+/// Returns `None` if `buf` does not contain the complete header block
+/// yet, otherwise returns the parsed headers along with the number of
+/// bytes that the header block took up.
+pub fn headers<'b, 'h>(
+    buf: &'b [u8],
+    headers: &'h mut [Header<'b>],
+) -> Result<Option<(&'h [Header<'b>], usize)>> {
+    let mut count = 0;
+    let mut offset = 0;
+
+    loop {
+        let (line, len) = match next_line(&buf[offset..])? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        offset += len;
+
+        if line.is_empty() {
+            return Ok(Some((&headers[..count], offset)));
+        }
+
+        let header = headers.get_mut(count).ok_or(Error::TooManyHeaders)?;
+        *header = parse_header(line)?;
+        count += 1;
+    }
+}
+
+// Split off the next CRLF-terminated line, returning it without the
+// CRLF along with the number of bytes consumed.
+fn next_line(buf: &[u8]) -> Result<Option<(&[u8], usize)>> {
+    let end = match memchr(b'\n', buf) {
+        Some(end) => end,
+        None => return Ok(None),
+    };
+
+    match end.checked_sub(1).map(|idx| buf[idx]) {
+        Some(b'\r') => Ok(Some((&buf[..end - 1], end + 1))),
+        _ => Err(Error::InvalidLineEnding),
+    }
+}
+
+fn parse_method(method: &[u8]) -> Result<Method<'_>> {
+    Ok(match method {
+        OPTIONS => Method::Options,
+        GET => Method::Get,
+        HEAD => Method::Head,
+        POST => Method::Post,
+        PUT => Method::Put,
+        PATCH => Method::Patch,
+        DELETE => Method::Delete,
+        TRACE => Method::Trace,
+        CONNECT => Method::Connect,
+        method => match str::from_utf8(method) {
+            Ok(method) if validate_method(method) => Method::Custom(method),
+            _ => return Err(Error::InvalidMethod),
+        },
+    })
+}
+
+fn parse_uri(uri: &[u8]) -> Result<Uri<'_>> {
+    if !validate_escaped_uri(uri) {
+        return Err(Error::InvalidUri);
+    }
+
+    // Safety: We just validated that the URI is correctly escaped.
+    Ok(unsafe { Uri::escaped_unchecked(uri) })
+}
+
+fn parse_version(version: &[u8]) -> Result<Version<'_>> {
+    Ok(match version {
+        HTTP_1_0 => Version::Http10,
+        HTTP_1_1 => Version::Http11,
+        version => match str::from_utf8(version) {
+            Ok(version) if validate_version(version) => Version::Custom(version),
+            _ => return Err(Error::InvalidVersion),
+        },
+    })
+}
+
+fn parse_status(status: &[u8]) -> Result<Status> {
+    match *status {
+        [a, b, c] if status.iter().all(u8::is_ascii_digit) => {
+            let digit = |c: u8| u16::from(c - b'0');
+
+            Ok(Status::new(digit(a) * 100 + digit(b) * 10 + digit(c)))
+        }
+        _ => Err(Error::InvalidStatus),
+    }
+}
+
+fn parse_reason(reason: &[u8]) -> Result<&str> {
+    if !validate_reason(reason) {
+        return Err(Error::InvalidReason);
+    }
+
+    str::from_utf8(reason).map_err(|_| Error::InvalidReason)
+}
+
+// Parse a single header line as defined by RFC7230.
+//
+// >     header-field   = field-name ":" OWS field-value OWS
+fn parse_header(line: &[u8]) -> Result<Header<'_>> {
+    let colon = memchr(b':', line).ok_or(Error::InvalidHeaderKey)?;
+    let (name, value) = (&line[..colon], &line[colon + 1..]);
+
+    if !validate_header_name(name) {
+        return Err(Error::InvalidHeaderKey);
+    }
+    if !validate_header_field(value) {
+        return Err(Error::InvalidHeaderValue);
+    }
+
+    Ok(Header {
+        name,
+        value: trim_ows(value),
+    })
+}
+
+// >     OWS            = *( SP / HTAB )
+fn trim_ows(mut value: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = value {
+        value = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = value {
+        value = rest;
+    }
+
+    value
+}
@@ -18,10 +18,53 @@ pub enum Error {
     InvalidUri,
     /// A custom version contains invalid characters
     InvalidVersion,
+    /// A reason phrase contained invalid characters
+    InvalidReason,
+    /// A status code was not made up of three digits
+    InvalidStatus,
+    /// A request or status line was not made up of the expected parts
+    InvalidStartLine,
+    /// A line was not terminated by CRLF
+    InvalidLineEnding,
+    /// There were more headers than there was space for
+    TooManyHeaders,
     /// A header key contained invalid characters
     InvalidHeaderKey,
     /// A header value contained invalid characters
     InvalidHeaderValue,
+    /// A chunk extension contained invalid characters
+    InvalidChunkExtension,
+    /// A trailer field is not allowed to be sent within a trailer
+    ForbiddenTrailer,
+    /// A trailer field was not declared within the `Trailer` header
+    UndeclaredTrailer,
+    /// The dynamic table capacity exceeds the maximum allowed by the peer
+    InvalidTableCapacity,
+    /// Making room within the dynamic table would evict an entry that is
+    /// still in use
+    TableFull,
+    /// A malformed or unexpected instruction was received from the peer
+    InvalidInstruction,
+    /// A framing header conflicts with the framing already declared for
+    /// the message
+    ConflictingFraming,
+    /// The message is not allowed to have a body
+    BodyNotAllowed,
+    /// The length of the body does not match the declared `Content-Length`
+    BodyLengthMismatch,
+    /// An HTTP/1.1 request did not have a `Host` header
+    MissingHost,
+    /// A request had more than one `Host` header
+    DuplicateHost,
+    /// The request target is not in the form required by the method
+    InvalidRequestTarget,
+    /// A multipart boundary contained invalid characters or was too long
+    InvalidBoundary,
+    /// A byte range lies outside of the representation
+    UnsatisfiableRange,
+    /// An authentication challenge only offered unsupported algorithms or
+    /// quality of protection values
+    UnsupportedChallenge,
 
     #[doc(hidden)]
     __Nonexhaustive(Empty),
@@ -36,8 +79,28 @@ impl Display for Error {
             InvalidMethod => "Invalid HTTP method",
             InvalidUri => "Invalid HTTP Uri",
             InvalidVersion => "Invalid HTTP Version",
+            InvalidReason => "Invalid reason phrase",
+            InvalidStatus => "Invalid status code",
+            InvalidStartLine => "Malformed request or status line",
+            InvalidLineEnding => "Line not terminated by CRLF",
+            TooManyHeaders => "Too many headers",
             InvalidHeaderKey => "Invalid header key",
             InvalidHeaderValue => "Invalid header value",
+            InvalidChunkExtension => "Invalid chunk extension",
+            ForbiddenTrailer => "Field not allowed in trailer",
+            UndeclaredTrailer => "Trailer field was not declared",
+            InvalidTableCapacity => "Invalid dynamic table capacity",
+            TableFull => "Dynamic table is full",
+            InvalidInstruction => "Invalid instruction",
+            ConflictingFraming => "Conflicting message framing",
+            BodyNotAllowed => "Message is not allowed to have a body",
+            BodyLengthMismatch => "Body length does not match Content-Length",
+            MissingHost => "Missing Host header",
+            DuplicateHost => "Duplicate Host header",
+            InvalidRequestTarget => "Invalid request target form for method",
+            InvalidBoundary => "Invalid multipart boundary",
+            UnsatisfiableRange => "Range not satisfiable",
+            UnsupportedChallenge => "Unsupported authentication challenge",
 
             &__Nonexhaustive(empty) => match empty {},
         })
@@ -46,3 +109,51 @@ impl Display for Error {
 
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
+
+/// Errors from writing a message out to an underlying writer.
+///
+/// Unlike [`Error`] this keeps the [`io::Error`](std::io::Error)
+/// returned by the writer or reader, which is available through
+/// [`source`](std::error::Error::source).
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum IoError {
+    /// The message could not be encoded
+    Encode(Error),
+    /// The underlying writer or reader failed
+    Io(std::io::Error),
+}
+
+#[cfg(feature = "std")]
+impl Display for IoError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            IoError::Encode(err) => Display::fmt(err, fmt),
+            IoError::Io(_) => fmt.write_str("I/O error"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IoError::Encode(_) => None,
+            IoError::Io(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Error> for IoError {
+    fn from(err: Error) -> Self {
+        IoError::Encode(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for IoError {
+    fn from(err: std::io::Error) -> Self {
+        IoError::Io(err)
+    }
+}
@@ -0,0 +1,242 @@
+//! A typed builder that enforces HTTP/1.1 message framing rules.
+//!
+//! [`FramedBuilder`] wraps an [`HttpBuilder`] and tracks, within its
+//! type, how the body of the message is delimited. Framing headers
+//! (`Content-Length` and `Transfer-Encoding`) can only be written through
+//! the dedicated methods and, once the framing has been declared, only
+//! the body method that matches it is available.
+//!
+//! ```
+//! # use httpencode::*;
+//! # use httpencode::framed::FramedBuilder;
+//! # fn main() -> Result<(), Error> {
+//! let mut buf = Vec::new();
+//! let mut builder = FramedBuilder::response(&mut buf, Version::Http11, Status::OK)?
+//!     .content_length(5)?;
+//! builder.header("Content-Type", "text/plain")?;
+//! builder.body(&mut &b"hello"[..])?;
+//!
+//! assert_eq!(
+//!     buf,
+//!     &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Type: text/plain\r\n\r\nhello"[..]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Calling a body method that doesn't match the declared framing is a
+//! compile error.
+//!
+//! ```compile_fail
+//! # use httpencode::*;
+//! # use httpencode::framed::FramedBuilder;
+//! # fn main() -> Result<(), Error> {
+//! let builder = FramedBuilder::bodyless_response(Vec::new(), Version::Http11, Status::NO_CONTENT)?;
+//! builder.body(&mut &b"hello"[..])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use core::marker::PhantomData;
+
+use bytes::{Buf, BufMut};
+
+use crate::{ChunkedWriter, Error, HeaderValue, HttpBuilder, Method, Result, Status, Uri, Version};
+
+/// No framing has been declared yet.
+#[derive(Copy, Clone, Debug)]
+pub enum Unframed {}
+
+/// The body is delimited by a `Content-Length` header.
+#[derive(Copy, Clone, Debug)]
+pub enum Length {}
+
+/// The body uses the chunked transfer coding.
+#[derive(Copy, Clone, Debug)]
+pub enum Chunked {}
+
+/// The message is not allowed to have a body.
+#[derive(Copy, Clone, Debug)]
+pub enum NoBody {}
+
+/// Builder that tracks the framing of the message body within its type.
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Debug)]
+pub struct FramedBuilder<B: BufMut, S> {
+    builder: HttpBuilder<B>,
+    length: u64,
+    state: PhantomData<S>,
+}
+
+/// Whether a response with this status code is forbidden from having a
+/// body.
+pub(crate) fn status_has_no_body(status: Status) -> bool {
+    matches!(status.code(), 100..=199 | 204 | 304)
+}
+
+/// Whether the header is one that determines the framing of the message.
+pub(crate) fn is_framing_header(key: &[u8]) -> bool {
+    key.eq_ignore_ascii_case(b"content-length") || key.eq_ignore_ascii_case(b"transfer-encoding")
+}
+
+impl<B: BufMut, S> FramedBuilder<B, S> {
+    fn transition<T>(self) -> FramedBuilder<B, T> {
+        FramedBuilder {
+            builder: self.builder,
+            length: self.length,
+            state: PhantomData,
+        }
+    }
+
+    /// Add a new header to the message.
+    ///
+    /// Framing headers must be declared through the dedicated methods,
+    /// attempting to write a `Content-Length` or `Transfer-Encoding`
+    /// header here will fail with [`Error::ConflictingFraming`].
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn header(&mut self, key: impl AsRef<[u8]>, val: impl HeaderValue) -> Result<&mut Self> {
+        let key = key.as_ref();
+
+        if is_framing_header(key) {
+            return Err(Error::ConflictingFraming);
+        }
+
+        self.builder.header(key, val)?;
+        Ok(self)
+    }
+
+    /// Get the underlying buffer for this message.
+    pub fn into_buf(self) -> B {
+        self.builder.into_buf()
+    }
+
+    /// Get the number of remaining bytes within the underlying buffer.
+    pub fn remaining(&self) -> usize {
+        self.builder.remaining()
+    }
+}
+
+impl<B: BufMut> FramedBuilder<B, Unframed> {
+    /// Create a new request with the provided request line.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn request(buf: B, method: Method, version: Version, uri: Uri) -> Result<Self> {
+        Ok(Self {
+            builder: HttpBuilder::request(buf, method, version, uri)?,
+            length: 0,
+            state: PhantomData,
+        })
+    }
+
+    /// Create a new response with the provided status.
+    ///
+    /// Responses with a 1xx, 204, or 304 status are not allowed to have a
+    /// body and must be created with [`FramedBuilder::bodyless_response`]
+    /// instead, passing one here fails with [`Error::BodyNotAllowed`].
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn response(buf: B, version: Version, status: Status) -> Result<Self> {
+        if status_has_no_body(status) {
+            return Err(Error::BodyNotAllowed);
+        }
+
+        Ok(Self {
+            builder: HttpBuilder::response(buf, version, status)?,
+            length: 0,
+            state: PhantomData,
+        })
+    }
+
+    /// Declare the length of the body by writing a `Content-Length`
+    /// header.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn content_length(mut self, length: u64) -> Result<FramedBuilder<B, Length>> {
+        self.builder.header("Content-Length", length)?;
+        self.length = length;
+        Ok(self.transition())
+    }
+
+    /// Declare that the body uses the chunked transfer coding by writing a
+    /// `Transfer-Encoding: chunked` header.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn chunked(mut self) -> Result<FramedBuilder<B, Chunked>> {
+        self.builder.header("Transfer-Encoding", "chunked")?;
+        Ok(self.transition())
+    }
+
+    /// Complete the message without declaring any framing.
+    ///
+    /// For requests this means that the message has no body. For
+    /// responses the body, if any, is delimited by closing the
+    /// connection.
+    pub fn finish(self) -> Result<B> {
+        self.builder.finish()
+    }
+}
+
+impl<B: BufMut> FramedBuilder<B, Length> {
+    /// Complete the message with a body of exactly the declared length.
+    ///
+    /// Fails with [`Error::BodyLengthMismatch`] if the length of `body`
+    /// does not match the declared `Content-Length`.
+    pub fn body<I: Buf>(self, body: &mut I) -> Result<B> {
+        if body.remaining() as u64 != self.length {
+            return Err(Error::BodyLengthMismatch);
+        }
+
+        self.builder.body(body)
+    }
+
+    /// The length of the body declared by the `Content-Length` header.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+impl<B: BufMut> FramedBuilder<B, Chunked> {
+    /// Complete the header and start writing the chunked body.
+    pub fn body(self) -> Result<ChunkedWriter<B>> {
+        self.builder.chunked()
+    }
+}
+
+impl<B: BufMut> FramedBuilder<B, NoBody> {
+    /// Create a new response that is not allowed to have a body.
+    ///
+    /// Only 1xx, 204, and 304 responses can be created this way, other
+    /// statuses fail with [`Error::InvalidStatus`].
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn bodyless_response(buf: B, version: Version, status: Status) -> Result<Self> {
+        if !status_has_no_body(status) {
+            return Err(Error::InvalidStatus);
+        }
+
+        Ok(Self {
+            builder: HttpBuilder::response(buf, version, status)?,
+            length: 0,
+            state: PhantomData,
+        })
+    }
+
+    /// Complete the message.
+    pub fn finish(self) -> Result<B> {
+        self.builder.finish()
+    }
+}
@@ -0,0 +1,358 @@
+use core::convert::TryInto;
+
+// Buffers input into the 64 byte blocks processed by both MD5 and
+// SHA-256, which also share the same padding scheme.
+#[derive(Clone)]
+struct Blocks {
+    block: [u8; 64],
+    len: usize,
+    total: u64,
+}
+
+impl Blocks {
+    fn new() -> Self {
+        Self {
+            block: [0; 64],
+            len: 0,
+            total: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; 64])) {
+        self.total = self.total.wrapping_add(data.len() as u64);
+
+        while !data.is_empty() {
+            let count = data.len().min(64 - self.len);
+            self.block[self.len..self.len + count].copy_from_slice(&data[..count]);
+            self.len += count;
+            data = &data[count..];
+
+            if self.len == 64 {
+                compress(&self.block);
+                self.len = 0;
+            }
+        }
+    }
+
+    // Pad out the final block with a single set bit, zeros, and the
+    // message length in bits.
+    fn finish(mut self, big_endian: bool, mut compress: impl FnMut(&[u8; 64])) {
+        let bits = self.total.wrapping_mul(8);
+        let len = if big_endian {
+            bits.to_be_bytes()
+        } else {
+            bits.to_le_bytes()
+        };
+
+        self.block[self.len] = 0x80;
+        self.block[self.len + 1..].iter_mut().for_each(|b| *b = 0);
+        if self.len >= 56 {
+            compress(&self.block);
+            self.block = [0; 64];
+        }
+
+        self.block[56..].copy_from_slice(&len);
+        compress(&self.block);
+    }
+}
+
+// Per-round shift amounts for MD5, as defined in RFC1321.
+#[rustfmt::skip]
+const MD5_SHIFTS: [u32; 16] = [
+    7, 12, 17, 22,
+    5, 9, 14, 20,
+    4, 11, 16, 23,
+    6, 10, 15, 21,
+];
+
+// The integer part of `abs(sin(i + 1)) * 2^32`.
+const MD5_TABLE: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+// MD5 as defined in RFC1321. This is only used for HTTP Digest
+// authentication, where it is still required for compatibility.
+#[derive(Clone)]
+pub(crate) struct Md5 {
+    state: [u32; 4],
+    blocks: Blocks,
+}
+
+impl Md5 {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            blocks: Blocks::new(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, |block| md5_compress(state, block));
+    }
+
+    pub(crate) fn finish(mut self) -> [u8; 16] {
+        let state = &mut self.state;
+        self.blocks
+            .finish(false, |block| md5_compress(state, block));
+
+        let mut out = [0; 16];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
+
+fn md5_compress(state: &mut [u32; 4], block: &[u8; 64]) {
+    let mut words = [0u32; 16];
+    for (word, chunk) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    let [mut a, mut b, mut c, mut d] = *state;
+    for i in 0..64 {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+
+        let f = f
+            .wrapping_add(a)
+            .wrapping_add(MD5_TABLE[i])
+            .wrapping_add(words[g]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[i / 16 * 4 + i % 4]));
+    }
+
+    for (state, value) in state.iter_mut().zip([a, b, c, d].iter()) {
+        *state = state.wrapping_add(*value);
+    }
+}
+
+// The first 32 bits of the fractional parts of the cube roots of the
+// first 64 primes.
+const SHA256_TABLE: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// SHA-256 as defined in RFC6234.
+#[derive(Clone)]
+pub(crate) struct Sha256 {
+    state: [u32; 8],
+    blocks: Blocks,
+}
+
+impl Sha256 {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            blocks: Blocks::new(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks
+            .update(data, |block| sha256_compress(state, block));
+    }
+
+    pub(crate) fn finish(mut self) -> [u8; 32] {
+        let state = &mut self.state;
+        self.blocks
+            .finish(true, |block| sha256_compress(state, block));
+
+        let mut out = [0; 32];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+}
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut words = [0u32; 64];
+    for (word, chunk) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    for i in 16..64 {
+        let s0 =
+            words[i - 15].rotate_right(7) ^ words[i - 15].rotate_right(18) ^ (words[i - 15] >> 3);
+        let s1 =
+            words[i - 2].rotate_right(17) ^ words[i - 2].rotate_right(19) ^ (words[i - 2] >> 10);
+        words[i] = words[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(words[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(SHA256_TABLE[i])
+            .wrapping_add(words[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (state, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+        *state = state.wrapping_add(*value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn md5(data: &[u8]) -> String {
+        let mut md5 = Md5::new();
+        md5.update(data);
+        hex(&md5.finish())
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        let mut sha256 = Sha256::new();
+        sha256.update(data);
+        hex(&sha256.finish())
+    }
+
+    // RFC1321 appendix A.5
+    #[test]
+    fn md5_test_suite() {
+        for &(input, expected) in &[
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ] {
+            assert_eq!(md5(input.as_bytes()), expected, "{:?}", input);
+        }
+    }
+
+    // FIPS 180-2 appendix B
+    #[test]
+    fn sha256_test_vectors() {
+        for &(input, expected) in &[
+            (
+                "",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                "abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ] {
+            assert_eq!(sha256(input.as_bytes()), expected, "{:?}", input);
+        }
+
+        let mut sha256 = Sha256::new();
+        for _ in 0..1000 {
+            sha256.update(&[b'a'; 1000]);
+        }
+        assert_eq!(
+            hex(&sha256.finish()),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    // Messages either side of where the length no longer fits in the final
+    // block, and of a whole block, whether written at once or byte by byte.
+    #[test]
+    fn padding_boundaries() {
+        for &(len, md5_expected, sha256_expected) in &[
+            (
+                55,
+                "ef1772b6dff9a122358552954ad0df65",
+                "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+            ),
+            (
+                56,
+                "3b0c8ac703f828b04c6c197006d17218",
+                "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a",
+            ),
+            (
+                63,
+                "b06521f39153d618550606be297466d5",
+                "7d3e74a05d7db15bce4ad9ec0658ea98e3f06eeecf16b4c6fff2da457ddc2f34",
+            ),
+            (
+                64,
+                "014842d480b571495a4a0363793f7367",
+                "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+            ),
+            (
+                65,
+                "c743a45e0d2e6a95cb859adae0248435",
+                "635361c48bb9eab14198e76ea8ab7f1a41685d6ad62aa9146d301d4f17eb0ae0",
+            ),
+        ] {
+            let data = [b'a'; 65];
+            assert_eq!(md5(&data[..len]), md5_expected, "{}", len);
+            assert_eq!(sha256(&data[..len]), sha256_expected, "{}", len);
+
+            let mut md5 = Md5::new();
+            let mut sha256 = Sha256::new();
+            for byte in data[..len].chunks(1) {
+                md5.update(byte);
+                sha256.update(byte);
+            }
+            assert_eq!(hex(&md5.finish()), md5_expected, "{}", len);
+            assert_eq!(hex(&sha256.finish()), sha256_expected, "{}", len);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Typed values for headers with a structured syntax.
+//!
+//! Each of these implements [`HeaderValue`](crate::HeaderValue) and checks
+//! the grammar of its header within
+//! [`validate`](crate::HeaderValue::validate), so invalid values are
+//! rejected by [`HttpBuilder::header`](crate::HttpBuilder::header).
+
+mod accept;
+mod auth;
+mod cache_control;
+mod cookie;
+mod date;
+mod digest;
+mod etag;
+mod range;
+
+pub use self::accept::{
+    Accept, AcceptCharset, AcceptEncoding, AcceptLanguage, MediaRange, QItem, QValue,
+};
+pub use self::auth::{
+    Authorization, Challenge, ProxyAuthenticate, ProxyAuthorization, WwwAuthenticate,
+};
+pub use self::cache_control::CacheControl;
+pub use self::cookie::{Cookie, SameSite, SetCookie};
+#[cfg(target_has_atomic = "64")]
+pub use self::date::DateCache;
+#[cfg(feature = "std")]
+pub use self::date::SystemClock;
+pub use self::date::{Clock, Date};
+pub use self::digest::{DigestAlgorithm, DigestAuth, DigestAuthorization, DigestChallenge, Qop};
+pub use self::etag::{EntityTag, EntityTags, IfRange};
+pub use self::range::{AcceptRanges, ByteRange, ContentRange, Range};
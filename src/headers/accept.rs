@@ -0,0 +1,269 @@
+use bytes::BufMut;
+
+use crate::traits::WriteValue;
+use crate::util::{quoted_string_len, try_write, validate_token, write_quoted};
+use crate::Result;
+
+/// A quality value (`q`) used to rank the preferences within a content
+/// negotiation header.
+///
+/// Quality values are stored in thousandths, the precision allowed by
+/// RFC 7231.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct QValue(u16);
+
+impl QValue {
+    /// The highest quality value, used when none is specified.
+    pub const ONE: QValue = QValue(1000);
+    /// A quality value of zero, marking the item as not acceptable.
+    pub const ZERO: QValue = QValue(0);
+
+    /// Create a quality value from thousandths, e.g. `700` for `0.7`.
+    ///
+    /// Returns `None` if the value is greater than `1000`.
+    pub fn from_thousandths(value: u16) -> Option<Self> {
+        if value > 1000 {
+            return None;
+        }
+
+        Some(Self(value))
+    }
+
+    /// The quality value in thousandths.
+    pub fn thousandths(self) -> u16 {
+        self.0
+    }
+
+    // Write out the `;q=` parameter. Nothing is written for the default
+    // value of 1. Trailing zeros are omitted so `500` is written as
+    // `0.5`.
+    fn write<B: BufMut>(self, buf: &mut B) -> Result<()> {
+        match self.0 {
+            1000 => Ok(()),
+            0 => try_write(buf, b";q=0"),
+            value => {
+                let digits = [
+                    b'0',
+                    b'.',
+                    b'0' + (value / 100) as u8,
+                    b'0' + (value / 10 % 10) as u8,
+                    b'0' + (value % 10) as u8,
+                ];
+                let mut len = digits.len();
+                while digits[len - 1] == b'0' {
+                    len -= 1;
+                }
+
+                try_write(buf, b";q=")?;
+                try_write(buf, &digits[..len])
+            }
+        }
+    }
+}
+
+impl Default for QValue {
+    fn default() -> Self {
+        Self::ONE
+    }
+}
+
+/// A media range within an `Accept` header, e.g.
+/// `text/html;level=1;q=0.7`.
+#[derive(Copy, Clone, Debug)]
+pub struct MediaRange<'a> {
+    kind: &'a str,
+    subtype: &'a str,
+    params: &'a [(&'a str, &'a str)],
+    q: QValue,
+}
+
+impl<'a> MediaRange<'a> {
+    /// Create a media range. Either part may be `*`.
+    pub fn new(kind: &'a str, subtype: &'a str) -> Self {
+        Self {
+            kind,
+            subtype,
+            params: &[],
+            q: QValue::ONE,
+        }
+    }
+
+    /// Set the media type parameters. Values that are not tokens are
+    /// written out as quoted strings.
+    pub fn params(mut self, params: &'a [(&'a str, &'a str)]) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Set the quality value.
+    pub fn q(mut self, q: QValue) -> Self {
+        self.q = q;
+        self
+    }
+
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        try_write(buf, self.kind.as_bytes())?;
+        try_write(buf, b"/")?;
+        try_write(buf, self.subtype.as_bytes())?;
+
+        for (name, value) in self.params {
+            try_write(buf, b";")?;
+            try_write(buf, name.as_bytes())?;
+            try_write(buf, b"=")?;
+
+            if validate_token(value.as_bytes()) {
+                try_write(buf, value.as_bytes())?;
+            } else {
+                write_quoted(buf, value.as_bytes())?;
+            }
+        }
+
+        self.q.write(buf)
+    }
+
+    fn validate(&self) -> bool {
+        // `*/subtype` is not a valid media range.
+        let wildcard = self.kind == "*" && self.subtype != "*";
+
+        !wildcard
+            && validate_token(self.kind.as_bytes())
+            && validate_token(self.subtype.as_bytes())
+            && self.params.iter().all(|(name, value)| {
+                // A parameter named `q` would be mistaken for the quality
+                // value.
+                validate_token(name.as_bytes())
+                    && !name.eq_ignore_ascii_case("q")
+                    && quoted_string_len(value.as_bytes()).is_some()
+            })
+    }
+}
+
+/// An item within a content negotiation header other than `Accept`, such as
+/// a content coding, a charset, or a language range.
+#[derive(Copy, Clone, Debug)]
+pub struct QItem<'a> {
+    value: &'a str,
+    q: QValue,
+}
+
+impl<'a> QItem<'a> {
+    /// Create an item with the default quality value of 1.
+    pub fn new(value: &'a str) -> Self {
+        Self {
+            value,
+            q: QValue::ONE,
+        }
+    }
+
+    /// Set the quality value.
+    pub fn q(mut self, q: QValue) -> Self {
+        self.q = q;
+        self
+    }
+
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        try_write(buf, self.value.as_bytes())?;
+        self.q.write(buf)
+    }
+}
+
+// Validate a language-range as defined by RFC4647 section 2.1.
+//
+// >  language-range   = (1*8ALPHA *("-" 1*8alphanum)) / "*"
+fn validate_language_range(range: &[u8]) -> bool {
+    if range == b"*" {
+        return true;
+    }
+
+    range.split(|&c| c == b'-').enumerate().all(|(i, subtag)| {
+        (1..=8).contains(&subtag.len())
+            && subtag.iter().all(|&c| {
+                if i == 0 {
+                    c.is_ascii_alphabetic()
+                } else {
+                    c.is_ascii_alphanumeric()
+                }
+            })
+    })
+}
+
+macro_rules! q_list {
+    {
+        $(
+            $( #[$attr:meta] )*
+            $name:ident: $item:ident => $validate:expr;
+        )*
+    } => {
+        $(
+            $( #[$attr] )*
+            #[derive(Copy, Clone, Debug)]
+            pub struct $name<'a> {
+                items: &'a [$item<'a>],
+            }
+
+            impl<'a> $name<'a> {
+                /// Create the header value from the provided items, in
+                /// order.
+                pub fn new(items: &'a [$item<'a>]) -> Self {
+                    Self { items }
+                }
+            }
+
+            impl WriteValue for $name<'_> {
+                fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+                    for (i, item) in self.items.iter().enumerate() {
+                        if i != 0 {
+                            try_write(buf, b", ")?;
+                        }
+
+                        item.write(buf)?;
+                    }
+
+                    Ok(())
+                }
+
+                fn is_valid(&self) -> bool {
+                    let validate: fn(&$item) -> bool = $validate;
+
+                    self.items.iter().all(validate)
+                }
+            }
+        )*
+    }
+}
+
+q_list! {
+    /// An `Accept` header value.
+    ///
+    /// ```
+    /// # use httpencode::*;
+    /// # use httpencode::headers::{Accept, MediaRange, QValue};
+    /// # fn main() -> Result<(), Error> {
+    /// let q = |q| QValue::from_thousandths(q).unwrap();
+    /// let accept = [
+    ///     MediaRange::new("text", "html"),
+    ///     MediaRange::new("application", "xml").q(q(900)),
+    ///     MediaRange::new("*", "*").q(q(800)),
+    /// ];
+    ///
+    /// let mut buf = Vec::new();
+    /// request::get(&mut buf, Uri::new(b"/"))?.header("Accept", Accept::new(&accept))?;
+    ///
+    /// assert_eq!(
+    ///     buf,
+    ///     &b"GET / HTTP/1.1\r\nAccept: text/html, application/xml;q=0.9, */*;q=0.8\r\n"[..]
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    Accept: MediaRange => |range| range.validate();
+
+    /// An `Accept-Encoding` header value made up of content codings.
+    AcceptEncoding: QItem => |item| validate_token(item.value.as_bytes());
+
+    /// An `Accept-Charset` header value made up of charsets.
+    AcceptCharset: QItem => |item| validate_token(item.value.as_bytes());
+
+    /// An `Accept-Language` header value made up of language ranges.
+    AcceptLanguage: QItem => |item| validate_language_range(item.value.as_bytes());
+}
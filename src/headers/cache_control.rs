@@ -0,0 +1,289 @@
+use bytes::BufMut;
+
+use crate::traits::WriteValue;
+use crate::util::{quoted_string_len, try_write, validate_token, write_quoted};
+use crate::{HeaderValue, Result};
+
+/// A `Cache-Control` header value as defined by RFC 7234, with the
+/// `immutable` (RFC 8246) and `stale-*` (RFC 5861) extensions.
+///
+/// Directives are always written in the same order, regardless of the
+/// order that they were set in, with any extensions last.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::CacheControl;
+/// # fn main() -> Result<(), Error> {
+/// let cache_control = CacheControl::new()
+///     .public()
+///     .max_age(3600)
+///     .stale_while_revalidate(60)
+///     .no_cache_fields(&["Set-Cookie"]);
+///
+/// let mut buf = Vec::new();
+/// response::ok(&mut buf)?.header("Cache-Control", cache_control)?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"HTTP/1.1 200 OK\r\n\
+///        Cache-Control: max-age=3600, no-cache=\"Set-Cookie\", public, \
+///        stale-while-revalidate=60\r\n"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CacheControl<'a> {
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+    max_stale: Option<Option<u64>>,
+    min_fresh: Option<u64>,
+    no_cache: Option<&'a [&'a str]>,
+    no_store: bool,
+    no_transform: bool,
+    only_if_cached: bool,
+    private: Option<&'a [&'a str]>,
+    public: bool,
+    must_revalidate: bool,
+    proxy_revalidate: bool,
+    immutable: bool,
+    stale_while_revalidate: Option<u64>,
+    stale_if_error: Option<u64>,
+    extensions: &'a [(&'a str, Option<&'a str>)],
+}
+
+impl<'a> CacheControl<'a> {
+    /// Create a value with no directives. At least one directive must be
+    /// set before the value is valid.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the `max-age` directive, in seconds.
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Set the `s-maxage` response directive, in seconds.
+    pub fn s_maxage(mut self, seconds: u64) -> Self {
+        self.s_maxage = Some(seconds);
+        self
+    }
+
+    /// Set the `max-stale` request directive. `None` accepts a stale
+    /// response of any age.
+    pub fn max_stale(mut self, seconds: Option<u64>) -> Self {
+        self.max_stale = Some(seconds);
+        self
+    }
+
+    /// Set the `min-fresh` request directive, in seconds.
+    pub fn min_fresh(mut self, seconds: u64) -> Self {
+        self.min_fresh = Some(seconds);
+        self
+    }
+
+    /// Set the `no-cache` directive.
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = Some(&[]);
+        self
+    }
+
+    /// Set the `no-cache` response directive, limited to the provided
+    /// header fields.
+    pub fn no_cache_fields(mut self, fields: &'a [&'a str]) -> Self {
+        self.no_cache = Some(fields);
+        self
+    }
+
+    /// Set the `no-store` directive.
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    /// Set the `no-transform` directive.
+    pub fn no_transform(mut self) -> Self {
+        self.no_transform = true;
+        self
+    }
+
+    /// Set the `only-if-cached` request directive.
+    pub fn only_if_cached(mut self) -> Self {
+        self.only_if_cached = true;
+        self
+    }
+
+    /// Set the `private` response directive.
+    pub fn private(mut self) -> Self {
+        self.private = Some(&[]);
+        self
+    }
+
+    /// Set the `private` response directive, limited to the provided
+    /// header fields.
+    pub fn private_fields(mut self, fields: &'a [&'a str]) -> Self {
+        self.private = Some(fields);
+        self
+    }
+
+    /// Set the `public` response directive.
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    /// Set the `must-revalidate` response directive.
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// Set the `proxy-revalidate` response directive.
+    pub fn proxy_revalidate(mut self) -> Self {
+        self.proxy_revalidate = true;
+        self
+    }
+
+    /// Set the `immutable` response directive.
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Set the `stale-while-revalidate` response directive, in seconds.
+    pub fn stale_while_revalidate(mut self, seconds: u64) -> Self {
+        self.stale_while_revalidate = Some(seconds);
+        self
+    }
+
+    /// Set the `stale-if-error` directive, in seconds.
+    pub fn stale_if_error(mut self, seconds: u64) -> Self {
+        self.stale_if_error = Some(seconds);
+        self
+    }
+
+    /// Set extension directives, written after all other directives.
+    /// Arguments that are not tokens are written out as quoted strings.
+    pub fn extensions(mut self, extensions: &'a [(&'a str, Option<&'a str>)]) -> Self {
+        self.extensions = extensions;
+        self
+    }
+}
+
+// Writes out the comma-separated list of directives.
+struct List<'b, B> {
+    buf: &'b mut B,
+    first: bool,
+}
+
+impl<B: BufMut> List<'_, B> {
+    fn flag(&mut self, name: &[u8], set: bool) -> Result<()> {
+        if !set {
+            return Ok(());
+        }
+
+        if !self.first {
+            try_write(self.buf, b", ")?;
+        }
+        self.first = false;
+
+        try_write(self.buf, name)
+    }
+
+    fn seconds(&mut self, name: &[u8], seconds: Option<u64>) -> Result<()> {
+        if let Some(seconds) = seconds {
+            self.flag(name, true)?;
+            try_write(self.buf, b"=")?;
+            seconds.put(self.buf)?;
+        }
+
+        Ok(())
+    }
+
+    // Write a directive that takes an optional list of field names as a
+    // quoted string, e.g. `no-cache="Set-Cookie, Authorization"`.
+    fn fields(&mut self, name: &[u8], fields: Option<&[&str]>) -> Result<()> {
+        let fields = match fields {
+            Some(fields) => fields,
+            None => return Ok(()),
+        };
+
+        self.flag(name, true)?;
+        if fields.is_empty() {
+            return Ok(());
+        }
+
+        // Field names are tokens and never need to be escaped.
+        try_write(self.buf, b"=\"")?;
+        for (i, field) in fields.iter().enumerate() {
+            if i != 0 {
+                try_write(self.buf, b", ")?;
+            }
+
+            try_write(self.buf, field.as_bytes())?;
+        }
+        try_write(self.buf, b"\"")
+    }
+
+    fn argument(&mut self, value: &[u8]) -> Result<()> {
+        if validate_token(value) {
+            try_write(self.buf, value)
+        } else {
+            write_quoted(self.buf, value)
+        }
+    }
+}
+
+impl WriteValue for CacheControl<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        let mut list = List { buf, first: true };
+
+        list.seconds(b"max-age", self.max_age)?;
+        list.seconds(b"s-maxage", self.s_maxage)?;
+        match self.max_stale {
+            Some(Some(seconds)) => list.seconds(b"max-stale", Some(seconds))?,
+            Some(None) => list.flag(b"max-stale", true)?,
+            None => (),
+        }
+        list.seconds(b"min-fresh", self.min_fresh)?;
+        list.fields(b"no-cache", self.no_cache)?;
+        list.flag(b"no-store", self.no_store)?;
+        list.flag(b"no-transform", self.no_transform)?;
+        list.flag(b"only-if-cached", self.only_if_cached)?;
+        list.fields(b"private", self.private)?;
+        list.flag(b"public", self.public)?;
+        list.flag(b"must-revalidate", self.must_revalidate)?;
+        list.flag(b"proxy-revalidate", self.proxy_revalidate)?;
+        list.flag(b"immutable", self.immutable)?;
+        list.seconds(b"stale-while-revalidate", self.stale_while_revalidate)?;
+        list.seconds(b"stale-if-error", self.stale_if_error)?;
+
+        for (name, value) in self.extensions {
+            list.flag(name.as_bytes(), true)?;
+
+            if let Some(value) = value {
+                try_write(list.buf, b"=")?;
+                list.argument(value.as_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        let fields = |fields: Option<&[&str]>| {
+            fields.is_none_or(|fields| fields.iter().all(|f| validate_token(f.as_bytes())))
+        };
+
+        // An empty `Cache-Control` header is not allowed.
+        self.encoded_len() != 0
+            && fields(self.no_cache)
+            && fields(self.private)
+            && self.extensions.iter().all(|(name, value)| {
+                validate_token(name.as_bytes())
+                    && value.is_none_or(|value| quoted_string_len(value.as_bytes()).is_some())
+            })
+    }
+}
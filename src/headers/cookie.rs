@@ -0,0 +1,263 @@
+use bytes::BufMut;
+
+use crate::traits::WriteValue;
+use crate::util::{try_write, validate_token};
+#[cfg(feature = "httpdate")]
+use crate::HttpDate;
+use crate::{HeaderValue, Result};
+
+/// Value of the `SameSite` cookie attribute.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            SameSite::Strict => b"Strict",
+            SameSite::Lax => b"Lax",
+            SameSite::None => b"None",
+        }
+    }
+}
+
+/// A `Set-Cookie` header value as defined by RFC 6265.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::{SameSite, SetCookie};
+/// # fn main() -> Result<(), Error> {
+/// let cookie = SetCookie::new("id", "a3fWa")
+///     .max_age(3600)
+///     .path("/")
+///     .secure()
+///     .http_only()
+///     .same_site(SameSite::Lax);
+///
+/// let mut buf = Vec::new();
+/// response::ok(&mut buf)?.header("Set-Cookie", cookie)?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"HTTP/1.1 200 OK\r\n\
+///        Set-Cookie: id=a3fWa; Max-Age=3600; Path=/; Secure; HttpOnly; SameSite=Lax\r\n"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct SetCookie<'a> {
+    name: &'a str,
+    value: &'a str,
+    #[cfg(feature = "httpdate")]
+    expires: Option<HttpDate>,
+    max_age: Option<i64>,
+    domain: Option<&'a str>,
+    path: Option<&'a str>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    partitioned: bool,
+}
+
+impl<'a> SetCookie<'a> {
+    /// Create a cookie with the provided name and value.
+    ///
+    /// The value may optionally be surrounded by double quotes, they are
+    /// considered part of the value and are written out as-is.
+    pub fn new(name: &'a str, value: &'a str) -> Self {
+        Self {
+            name,
+            value,
+            #[cfg(feature = "httpdate")]
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            partitioned: false,
+        }
+    }
+
+    /// Set the `Expires` attribute.
+    #[cfg(feature = "httpdate")]
+    pub fn expires(mut self, expires: HttpDate) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Set the `Max-Age` attribute, in seconds. A value of zero or less
+    /// expires the cookie immediately.
+    pub fn max_age(mut self, seconds: i64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Set the `Domain` attribute.
+    pub fn domain(mut self, domain: &'a str) -> Self {
+        self.domain = Some(domain);
+        self
+    }
+
+    /// Set the `Path` attribute.
+    pub fn path(mut self, path: &'a str) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// Set the `Secure` attribute.
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    /// Set the `HttpOnly` attribute.
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Set the `Partitioned` attribute.
+    pub fn partitioned(mut self) -> Self {
+        self.partitioned = true;
+        self
+    }
+}
+
+impl WriteValue for SetCookie<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        write_pair(buf, self.name, self.value)?;
+
+        #[cfg(feature = "httpdate")]
+        if let Some(expires) = self.expires {
+            try_write(buf, b"; Expires=")?;
+            expires.put(buf)?;
+        }
+        if let Some(max_age) = self.max_age {
+            try_write(buf, b"; Max-Age=")?;
+            max_age.put(buf)?;
+        }
+        if let Some(domain) = self.domain {
+            try_write(buf, b"; Domain=")?;
+            try_write(buf, domain.as_bytes())?;
+        }
+        if let Some(path) = self.path {
+            try_write(buf, b"; Path=")?;
+            try_write(buf, path.as_bytes())?;
+        }
+        if self.secure {
+            try_write(buf, b"; Secure")?;
+        }
+        if self.http_only {
+            try_write(buf, b"; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            try_write(buf, b"; SameSite=")?;
+            try_write(buf, same_site.as_bytes())?;
+        }
+        if self.partitioned {
+            try_write(buf, b"; Partitioned")?;
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        validate_token(self.name.as_bytes())
+            && validate_cookie_value(self.value.as_bytes())
+            && self.domain.is_none_or(validate_av_value)
+            && self.path.is_none_or(validate_av_value)
+    }
+}
+
+/// A `Cookie` header value made up of a list of name-value pairs.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::Cookie;
+/// # fn main() -> Result<(), Error> {
+/// let mut buf = Vec::new();
+/// request::get(&mut buf, Uri::new(b"/"))?
+///     .header("Cookie", Cookie::new(&[("id", "a3fWa"), ("theme", "dark")]))?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"GET / HTTP/1.1\r\nCookie: id=a3fWa; theme=dark\r\n"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Cookie<'a> {
+    pairs: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> Cookie<'a> {
+    /// Create a cookie list from name-value pairs.
+    pub fn new(pairs: &'a [(&'a str, &'a str)]) -> Self {
+        Self { pairs }
+    }
+}
+
+impl WriteValue for Cookie<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        for (i, (name, value)) in self.pairs.iter().enumerate() {
+            if i != 0 {
+                try_write(buf, b"; ")?;
+            }
+
+            write_pair(buf, name, value)?;
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        !self.pairs.is_empty()
+            && self.pairs.iter().all(|(name, value)| {
+                validate_token(name.as_bytes()) && validate_cookie_value(value.as_bytes())
+            })
+    }
+}
+
+fn write_pair<B: BufMut>(buf: &mut B, name: &str, value: &str) -> Result<()> {
+    try_write(buf, name.as_bytes())?;
+    try_write(buf, b"=")?;
+    try_write(buf, value.as_bytes())
+}
+
+// Validate a cookie-value as defined by RFC6265 section 4.1.1.
+//
+// >  cookie-value      = *cookie-octet / ( DQUOTE *cookie-octet DQUOTE )
+// >  cookie-octet      = %x21 / %x23-2B / %x2D-3A / %x3C-5B / %x5D-7E
+fn validate_cookie_value(value: &[u8]) -> bool {
+    let value = match value {
+        [b'"', inner @ .., b'"'] => inner,
+        value => value,
+    };
+
+    value
+        .iter()
+        .all(|&c| matches!(c, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E))
+}
+
+// Validate the value of the `Domain` or `Path` attributes.
+//
+// >  path-value        = <any CHAR except CTLs or ";">
+fn validate_av_value(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|c| (0x20..0x7F).contains(&c) && c != b';')
+}
@@ -0,0 +1,234 @@
+use bytes::BufMut;
+use core::convert::TryInto;
+use core::fmt;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+
+use crate::traits::OutOfBufferError;
+use crate::HeaderValue;
+
+const DATE_LEN: usize = 29;
+
+// The last second that can be represented by a 4 digit year,
+// 9999-12-31T23:59:59Z.
+const MAX_SECONDS: u64 = 253_402_300_799;
+
+/// A source of the current time.
+///
+/// This is implemented for any closure returning the number of seconds
+/// since the Unix epoch, which allows tests to inject a fixed time and
+/// `no_std` users to supply their own.
+pub trait Clock {
+    /// The number of seconds since the Unix epoch.
+    fn unix_seconds(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn unix_seconds(&self) -> u64 {
+        self()
+    }
+}
+
+/// A [`Clock`] that reads the system time.
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn unix_seconds(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// A date formatted as an IMF-fixdate, e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, for use within the `Date` header.
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Date {
+    data: [u8; DATE_LEN],
+}
+
+impl Date {
+    /// Format the given number of seconds since the Unix epoch.
+    ///
+    /// Times after the year 9999 are clamped to the end of that year.
+    pub fn from_unix_seconds(seconds: u64) -> Self {
+        const DAYS: [&[u8; 3]; 7] = [b"Sun", b"Mon", b"Tue", b"Wed", b"Thu", b"Fri", b"Sat"];
+        const MONTHS: [&[u8; 3]; 12] = [
+            b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov",
+            b"Dec",
+        ];
+
+        let seconds = seconds.min(MAX_SECONDS);
+        let days = seconds / 86400;
+        let secs = seconds % 86400;
+
+        // Convert days since the epoch into a civil date, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as u64;
+
+        // 1970-01-01 was a Thursday.
+        let weekday = (days + 4) % 7;
+
+        let mut data = *b"Thu, 01 Jan 1970 00:00:00 GMT";
+        data[0..3].copy_from_slice(DAYS[weekday as usize]);
+        write_digits(&mut data[5..7], day);
+        data[8..11].copy_from_slice(MONTHS[month as usize - 1]);
+        write_digits(&mut data[12..16], year);
+        write_digits(&mut data[17..19], secs / 3600);
+        write_digits(&mut data[20..22], secs / 60 % 60);
+        write_digits(&mut data[23..25], secs % 60);
+
+        Self { data }
+    }
+
+    /// Get the formatted date.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+// Write `num` as zero-padded decimal digits filling `out`.
+fn write_digits(out: &mut [u8], mut num: u64) {
+    for digit in out.iter_mut().rev() {
+        *digit = b'0' + (num % 10) as u8;
+        num /= 10;
+    }
+}
+
+impl fmt::Debug for Date {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // The formatted date is always ASCII.
+        fmt.write_str(core::str::from_utf8(&self.data).unwrap())
+    }
+}
+
+impl HeaderValue for Date {
+    fn put<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
+        self.as_bytes().put(buf)
+    }
+
+    fn est_len(&self) -> Option<usize> {
+        Some(DATE_LEN)
+    }
+
+    fn encoded_len(&self) -> usize {
+        DATE_LEN
+    }
+}
+
+// The formatted date is cached as whole words so that it can be read and
+// written atomically.
+#[cfg(target_has_atomic = "64")]
+const WORDS: usize = DATE_LEN.div_ceil(8);
+
+/// Caches the formatted `Date` so that it is formatted at most once per
+/// second.
+///
+/// The cache can be shared between threads and never takes a lock. The
+/// cached date is guarded by a seqlock: a read that races with an update,
+/// or an update that races with another update, formats the date itself
+/// instead of waiting.
+#[cfg(target_has_atomic = "64")]
+#[derive(Debug)]
+pub struct DateCache<C: Clock> {
+    clock: C,
+    // The cached second shifted left by one, with the low bit set while
+    // the cached date is being updated.
+    stamp: AtomicU64,
+    date: [AtomicU64; WORDS],
+}
+
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+impl DateCache<SystemClock> {
+    /// Create a new cache that reads the system time.
+    pub fn system() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl<C: Clock> DateCache<C> {
+    /// Create a new cache reading the time from `clock`.
+    pub fn new(clock: C) -> Self {
+        let now = clock.unix_seconds().min(MAX_SECONDS);
+        let date = pack(&Date::from_unix_seconds(now));
+
+        Self {
+            clock,
+            stamp: AtomicU64::new(now << 1),
+            date: date.map(AtomicU64::new),
+        }
+    }
+
+    /// Get the current date.
+    pub fn now(&self) -> Date {
+        let now = self.clock.unix_seconds().min(MAX_SECONDS);
+
+        let stamp = self.stamp.load(Ordering::Acquire);
+        if stamp == now << 1 {
+            let words = self
+                .date
+                .each_ref()
+                .map(|word| word.load(Ordering::Relaxed));
+
+            fence(Ordering::Acquire);
+            if self.stamp.load(Ordering::Relaxed) == stamp {
+                return unpack(words);
+            }
+        }
+
+        let date = Date::from_unix_seconds(now);
+
+        // Only ever move the cache forwards, and leave it to the other
+        // thread if it is already being updated.
+        let updating = now << 1 | 1;
+        if stamp & 1 == 0
+            && stamp >> 1 < now
+            && self
+                .stamp
+                .compare_exchange(stamp, updating, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            fence(Ordering::Release);
+            for (word, value) in self.date.iter().zip(pack(&date)) {
+                word.store(value, Ordering::Relaxed);
+            }
+            self.stamp.store(now << 1, Ordering::Release);
+        }
+
+        date
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+fn pack(date: &Date) -> [u64; WORDS] {
+    let mut bytes = [0; WORDS * 8];
+    bytes[..DATE_LEN].copy_from_slice(&date.data);
+
+    core::array::from_fn(|i| u64::from_ne_bytes(bytes[i * 8..][..8].try_into().unwrap()))
+}
+
+#[cfg(target_has_atomic = "64")]
+fn unpack(words: [u64; WORDS]) -> Date {
+    let mut bytes = [0; WORDS * 8];
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(words) {
+        chunk.copy_from_slice(&word.to_ne_bytes());
+    }
+
+    let mut data = [0; DATE_LEN];
+    data.copy_from_slice(&bytes[..DATE_LEN]);
+
+    Date { data }
+}
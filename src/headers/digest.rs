@@ -0,0 +1,665 @@
+use bytes::BufMut;
+use core::fmt;
+
+use memchr::memchr;
+
+use crate::hash::{Md5, Sha256};
+use crate::traits::WriteValue;
+use crate::util::{
+    is_tchar, method_bytes, quoted_string_len, try_write, write_quoted, write_uri, Sink,
+};
+use crate::{Error, Method, Result, Uri};
+
+/// The hash algorithm used for Digest authentication, as defined by
+/// RFC 7616.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DigestAlgorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl DigestAlgorithm {
+    fn parse(name: &[u8]) -> Option<Self> {
+        const ALGORITHMS: [DigestAlgorithm; 4] = [
+            DigestAlgorithm::Md5,
+            DigestAlgorithm::Md5Sess,
+            DigestAlgorithm::Sha256,
+            DigestAlgorithm::Sha256Sess,
+        ];
+
+        ALGORITHMS
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.as_bytes().eq_ignore_ascii_case(name))
+    }
+
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            DigestAlgorithm::Md5 => b"MD5",
+            DigestAlgorithm::Md5Sess => b"MD5-sess",
+            DigestAlgorithm::Sha256 => b"SHA-256",
+            DigestAlgorithm::Sha256Sess => b"SHA-256-sess",
+        }
+    }
+
+    fn is_session(self) -> bool {
+        matches!(self, DigestAlgorithm::Md5Sess | DigestAlgorithm::Sha256Sess)
+    }
+}
+
+/// The quality of protection used for Digest authentication.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Qop {
+    /// Authentication only.
+    Auth,
+    /// Authentication with integrity protection of the request body.
+    AuthInt,
+}
+
+impl Qop {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Qop::Auth => b"auth",
+            Qop::AuthInt => b"auth-int",
+        }
+    }
+}
+
+/// A `Digest` challenge parsed from a `WWW-Authenticate` or
+/// `Proxy-Authenticate` header value.
+///
+/// Quoted values are borrowed from the header value without being
+/// unescaped, so they can be written back out as-is.
+#[derive(Copy, Clone, Debug)]
+pub struct DigestChallenge<'a> {
+    realm: &'a [u8],
+    nonce: &'a [u8],
+    opaque: Option<&'a [u8]>,
+    algorithm: DigestAlgorithm,
+    auth: bool,
+    auth_int: bool,
+    legacy: bool,
+    userhash: bool,
+}
+
+impl<'a> DigestChallenge<'a> {
+    /// Parse the first supported `Digest` challenge within a header value,
+    /// skipping over challenges for other schemes.
+    ///
+    /// Fails with [`Error::InvalidHeaderValue`] if the header value is
+    /// malformed and with [`Error::UnsupportedChallenge`] if there is no
+    /// `Digest` challenge using a supported algorithm and quality of
+    /// protection.
+    pub fn parse(value: &'a [u8]) -> Result<Self> {
+        let mut parser = Parser { rest: value };
+
+        loop {
+            parser.skip(|c| matches!(c, b',' | b' ' | b'\t'));
+            if parser.rest.is_empty() {
+                return Err(Error::UnsupportedChallenge);
+            }
+
+            let scheme = parser.token().ok_or(Error::InvalidHeaderValue)?;
+            let mut challenge = Challenge::default();
+
+            if parser.skip(|c| c == b' ') {
+                if let Some((name, value)) = parser.param() {
+                    challenge.set(name, value);
+
+                    while parser.next_param() {
+                        let (name, value) = parser.param().ok_or(Error::InvalidHeaderValue)?;
+                        challenge.set(name, value);
+                    }
+                } else if !parser.token68() {
+                    return Err(Error::InvalidHeaderValue);
+                }
+            }
+
+            // Anything other than the end of the value or another
+            // challenge is a syntax error.
+            parser.skip(|c| c == b' ' || c == b'\t');
+            if !parser.rest.is_empty() && !parser.rest.starts_with(b",") {
+                return Err(Error::InvalidHeaderValue);
+            }
+
+            if scheme.eq_ignore_ascii_case(b"digest") {
+                if let Some(challenge) = challenge.into_digest() {
+                    return Ok(challenge);
+                }
+            }
+        }
+    }
+
+    /// The hash algorithm requested by the server.
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        self.algorithm
+    }
+}
+
+// The parameters of a challenge that are relevant to Digest
+// authentication.
+#[derive(Default)]
+struct Challenge<'a> {
+    realm: Option<&'a [u8]>,
+    nonce: Option<&'a [u8]>,
+    opaque: Option<&'a [u8]>,
+    algorithm: Option<&'a [u8]>,
+    qop: Option<&'a [u8]>,
+    userhash: bool,
+}
+
+impl<'a> Challenge<'a> {
+    fn set(&mut self, name: &[u8], value: &'a [u8]) {
+        let field = if name.eq_ignore_ascii_case(b"realm") {
+            &mut self.realm
+        } else if name.eq_ignore_ascii_case(b"nonce") {
+            &mut self.nonce
+        } else if name.eq_ignore_ascii_case(b"opaque") {
+            &mut self.opaque
+        } else if name.eq_ignore_ascii_case(b"algorithm") {
+            &mut self.algorithm
+        } else if name.eq_ignore_ascii_case(b"qop") {
+            &mut self.qop
+        } else if name.eq_ignore_ascii_case(b"userhash") {
+            self.userhash = value.eq_ignore_ascii_case(b"true");
+            return;
+        } else {
+            return;
+        };
+
+        *field = Some(value);
+    }
+
+    fn into_digest(self) -> Option<DigestChallenge<'a>> {
+        let algorithm = match self.algorithm {
+            Some(algorithm) => DigestAlgorithm::parse(algorithm)?,
+            None => DigestAlgorithm::Md5,
+        };
+
+        // A challenge without a qop parameter uses the RFC2069 scheme,
+        // which is still supported for compatibility.
+        let legacy = self.qop.is_none();
+        let (mut auth, mut auth_int) = (legacy, false);
+        for qop in self.qop.unwrap_or_default().split(|&c| c == b',') {
+            match trim(qop) {
+                b"auth" => auth = true,
+                b"auth-int" => auth_int = true,
+                _ => (),
+            }
+        }
+
+        if !auth && !auth_int {
+            return None;
+        }
+
+        Some(DigestChallenge {
+            realm: self.realm?,
+            nonce: self.nonce?,
+            opaque: self.opaque,
+            algorithm,
+            auth,
+            auth_int,
+            legacy,
+            userhash: self.userhash,
+        })
+    }
+}
+
+/// Computes the `Authorization` header for requests authenticated with
+/// the Digest scheme, as defined by RFC 7616.
+///
+/// The nonce count is incremented for every request and reset whenever
+/// a challenge with a new nonce is provided.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::{DigestAuth, DigestChallenge};
+/// # fn main() -> Result<(), Error> {
+/// let challenge = DigestChallenge::parse(b"Digest realm=\"api\", qop=\"auth\", nonce=\"abc\"")?;
+/// let mut auth = DigestAuth::new(challenge, "user", "secret");
+///
+/// let mut buf = Vec::new();
+/// let uri = Uri::new(b"/");
+/// let authorization = auth.authorize(Method::Get, uri, "0a4f113b", None)?;
+/// request::get(&mut buf, uri)?.header("Authorization", authorization)?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"GET / HTTP/1.1\r\n\
+///        Authorization: Digest username=\"user\", realm=\"api\", uri=\"/\", algorithm=MD5, \
+///        nonce=\"abc\", nc=00000001, cnonce=\"0a4f113b\", qop=auth, \
+///        response=\"0f0a0aa212ad2bd433f770272baec74d\"\r\n"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DigestAuth<'a> {
+    challenge: DigestChallenge<'a>,
+    username: &'a str,
+    password: &'a str,
+    nc: u32,
+}
+
+impl<'a> DigestAuth<'a> {
+    /// Create an authenticator for the given challenge and credentials.
+    pub fn new(challenge: DigestChallenge<'a>, username: &'a str, password: &'a str) -> Self {
+        Self {
+            challenge,
+            username,
+            password,
+            nc: 0,
+        }
+    }
+
+    /// Replace the challenge, for example after the server has reported
+    /// that the nonce is stale. The nonce count is reset if the nonce has
+    /// changed.
+    pub fn update_challenge(&mut self, challenge: DigestChallenge<'a>) {
+        if challenge.nonce != self.challenge.nonce {
+            self.nc = 0;
+        }
+
+        self.challenge = challenge;
+    }
+
+    /// The nonce count sent with the most recent request.
+    pub fn nonce_count(&self) -> u32 {
+        self.nc
+    }
+
+    /// Compute the `Authorization` header value for a request. `method`
+    /// and `uri` must be the same as those within the request line.
+    ///
+    /// The client nonce should be a new random value for every request.
+    /// If `body` is provided and the server offers `auth-int` then the
+    /// request body is also protected, otherwise `auth` is used.
+    ///
+    /// Fails with [`Error::InvalidHeaderValue`] if the username or
+    /// client nonce can't be written as a quoted string and with
+    /// [`Error::UnsupportedChallenge`] if `body` is not provided but the
+    /// server only offers `auth-int`.
+    pub fn authorize<'r>(
+        &mut self,
+        method: Method,
+        uri: Uri<'r>,
+        cnonce: &'r str,
+        body: Option<&[u8]>,
+    ) -> Result<DigestAuthorization<'r>>
+    where
+        'a: 'r,
+    {
+        let challenge = &self.challenge;
+        let qop = match (challenge.auth, challenge.auth_int, body) {
+            (_, true, Some(_)) => Qop::AuthInt,
+            (true, _, _) => Qop::Auth,
+            _ => return Err(Error::UnsupportedChallenge),
+        };
+        // Challenges without a qop parameter use neither a client nonce
+        // nor a nonce count.
+        let legacy = challenge.legacy;
+
+        let userhash = if challenge.userhash {
+            let mut hasher = Hasher::new(challenge.algorithm);
+            hasher.update(self.username.as_bytes());
+            hasher.update(b":");
+            hasher.update_unescaped(challenge.realm);
+            Some(hasher.finish())
+        } else if quoted_string_len(self.username.as_bytes()).is_none() {
+            return Err(Error::InvalidHeaderValue);
+        } else {
+            None
+        };
+
+        if quoted_string_len(cnonce.as_bytes()).is_none() {
+            return Err(Error::InvalidHeaderValue);
+        }
+
+        let nc = self.nc.wrapping_add(1);
+        let nc_hex = hex_u32(nc);
+
+        let mut hasher = Hasher::new(challenge.algorithm);
+        hasher.update(self.username.as_bytes());
+        hasher.update(b":");
+        hasher.update_unescaped(challenge.realm);
+        hasher.update(b":");
+        hasher.update(self.password.as_bytes());
+        let mut ha1 = hasher.finish();
+
+        if challenge.algorithm.is_session() {
+            let mut hasher = Hasher::new(challenge.algorithm);
+            hasher.update(ha1.as_bytes());
+            hasher.update(b":");
+            hasher.update_unescaped(challenge.nonce);
+            hasher.update(b":");
+            hasher.update(cnonce.as_bytes());
+            ha1 = hasher.finish();
+        }
+
+        let mut hasher = Hasher::new(challenge.algorithm);
+        hasher.update(method_bytes(method)?);
+        hasher.update(b":");
+        write_uri(&mut Sink::new(|bytes: &[u8]| hasher.update(bytes)), uri)?;
+        if let (Qop::AuthInt, Some(body)) = (qop, body) {
+            let mut body_hasher = Hasher::new(challenge.algorithm);
+            body_hasher.update(body);
+            hasher.update(b":");
+            hasher.update(body_hasher.finish().as_bytes());
+        }
+        let ha2 = hasher.finish();
+
+        let mut hasher = Hasher::new(challenge.algorithm);
+        hasher.update(ha1.as_bytes());
+        hasher.update(b":");
+        hasher.update_unescaped(challenge.nonce);
+        hasher.update(b":");
+        if !legacy {
+            hasher.update(&nc_hex);
+            hasher.update(b":");
+            hasher.update(cnonce.as_bytes());
+            hasher.update(b":");
+            hasher.update(qop.as_bytes());
+            hasher.update(b":");
+        }
+        hasher.update(ha2.as_bytes());
+        let response = hasher.finish();
+
+        self.nc = nc;
+
+        Ok(DigestAuthorization {
+            username: self.username,
+            userhash,
+            realm: challenge.realm,
+            uri,
+            algorithm: challenge.algorithm,
+            nonce: challenge.nonce,
+            protection: if legacy {
+                None
+            } else {
+                Some((nc, cnonce, qop))
+            },
+            response,
+            opaque: challenge.opaque,
+        })
+    }
+}
+
+/// An `Authorization` header value for the Digest scheme, as computed by
+/// [`DigestAuth::authorize`].
+#[derive(Copy, Clone, Debug)]
+pub struct DigestAuthorization<'a> {
+    username: &'a str,
+    userhash: Option<Hex>,
+    realm: &'a [u8],
+    uri: Uri<'a>,
+    algorithm: DigestAlgorithm,
+    nonce: &'a [u8],
+    protection: Option<(u32, &'a str, Qop)>,
+    response: Hex,
+    opaque: Option<&'a [u8]>,
+}
+
+impl WriteValue for DigestAuthorization<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        try_write(buf, b"Digest username=")?;
+        match &self.userhash {
+            Some(userhash) => write_quoted(buf, userhash.as_bytes())?,
+            None => write_quoted(buf, self.username.as_bytes())?,
+        }
+
+        // The realm and nonce are still escaped from the challenge.
+        try_write(buf, b", realm=\"")?;
+        try_write(buf, self.realm)?;
+        try_write(buf, b"\", uri=\"")?;
+        write_uri(buf, self.uri)?;
+        try_write(buf, b"\", algorithm=")?;
+        try_write(buf, self.algorithm.as_bytes())?;
+        try_write(buf, b", nonce=\"")?;
+        try_write(buf, self.nonce)?;
+        try_write(buf, b"\"")?;
+
+        if let Some((nc, cnonce, qop)) = self.protection {
+            try_write(buf, b", nc=")?;
+            try_write(buf, &hex_u32(nc))?;
+            try_write(buf, b", cnonce=")?;
+            write_quoted(buf, cnonce.as_bytes())?;
+            try_write(buf, b", qop=")?;
+            try_write(buf, qop.as_bytes())?;
+        }
+
+        try_write(buf, b", response=\"")?;
+        try_write(buf, self.response.as_bytes())?;
+        try_write(buf, b"\"")?;
+
+        if let Some(opaque) = self.opaque {
+            try_write(buf, b", opaque=\"")?;
+            try_write(buf, opaque)?;
+            try_write(buf, b"\"")?;
+        }
+        if self.userhash.is_some() {
+            try_write(buf, b", userhash=true")?;
+        }
+
+        Ok(())
+    }
+}
+
+// Format the nonce count as 8 lowercase hex digits.
+fn hex_u32(value: u32) -> [u8; 8] {
+    let mut out = [0; 8];
+    for (i, digit) in out.iter_mut().enumerate() {
+        *digit = HEX_DIGITS[(value >> (28 - 4 * i)) as usize & 0xF];
+    }
+    out
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+// A digest formatted as lowercase hex.
+#[derive(Copy, Clone)]
+struct Hex {
+    data: [u8; 64],
+    len: usize,
+}
+
+impl Hex {
+    fn new(digest: &[u8]) -> Self {
+        let mut data = [0; 64];
+        for (out, &byte) in data.chunks_exact_mut(2).zip(digest) {
+            out[0] = HEX_DIGITS[usize::from(byte >> 4)];
+            out[1] = HEX_DIGITS[usize::from(byte & 0xF)];
+        }
+
+        Self {
+            data,
+            len: digest.len() * 2,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl fmt::Debug for Hex {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // Hex digits are always ASCII.
+        fmt.write_str(core::str::from_utf8(self.as_bytes()).unwrap())
+    }
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            DigestAlgorithm::Md5 | DigestAlgorithm::Md5Sess => Hasher::Md5(Md5::new()),
+            DigestAlgorithm::Sha256 | DigestAlgorithm::Sha256Sess => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    // Hash the contents of a quoted-string, without the quoted-pair
+    // escapes.
+    fn update_unescaped(&mut self, mut value: &[u8]) {
+        while let Some(i) = memchr(b'\\', value) {
+            self.update(&value[..i]);
+            self.update(&value[i + 1..(i + 2).min(value.len())]);
+            value = &value[(i + 2).min(value.len())..];
+        }
+
+        self.update(value);
+    }
+
+    fn finish(self) -> Hex {
+        match self {
+            Hasher::Md5(hasher) => Hex::new(&hasher.finish()),
+            Hasher::Sha256(hasher) => Hex::new(&hasher.finish()),
+        }
+    }
+}
+
+fn trim(value: &[u8]) -> &[u8] {
+    let start = value
+        .iter()
+        .position(|&c| c != b' ' && c != b'\t')
+        .unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|&c| c != b' ' && c != b'\t')
+        .map_or(start, |i| i + 1);
+
+    &value[start..end]
+}
+
+// Parser for the challenge syntax from RFC7235 section 2.1.
+//
+// >     challenge      = auth-scheme [ 1*SP ( token68 / #auth-param ) ]
+// >     auth-param     = token BWS "=" BWS ( token / quoted-string )
+// >     token68        = 1*( ALPHA / DIGIT /
+// >                          "-" / "." / "_" / "~" / "+" / "/" ) *"="
+struct Parser<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    // Skip over characters matching `pred`, returning whether any were
+    // skipped.
+    fn skip(&mut self, pred: impl Fn(u8) -> bool) -> bool {
+        let len = self
+            .rest
+            .iter()
+            .position(|&c| !pred(c))
+            .unwrap_or(self.rest.len());
+        self.rest = &self.rest[len..];
+
+        len != 0
+    }
+
+    fn take(&mut self, pred: impl Fn(u8) -> bool) -> &'a [u8] {
+        let rest = self.rest;
+        self.skip(pred);
+
+        &rest[..rest.len() - self.rest.len()]
+    }
+
+    fn token(&mut self) -> Option<&'a [u8]> {
+        Some(self.take(is_tchar)).filter(|token| !token.is_empty())
+    }
+
+    fn token68(&mut self) -> bool {
+        let token = self.take(|c| c.is_ascii_alphanumeric() || b"-._~+/".contains(&c));
+        self.skip(|c| c == b'=');
+
+        !token.is_empty()
+    }
+
+    // Parse a quoted-string, returning its contents with any escapes.
+    fn quoted_string(&mut self) -> Option<&'a [u8]> {
+        let rest = self.rest.strip_prefix(b"\"")?;
+
+        let mut i = 0;
+        while i < rest.len() {
+            match rest[i] {
+                b'"' => {
+                    self.rest = &rest[i + 1..];
+                    return Some(&rest[..i]);
+                }
+                b'\\' if rest.get(i + 1).is_some_and(|&c| is_qdchar(c) || c == b'"') => i += 2,
+                c if is_qdchar(c) && c != b'\\' => i += 1,
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    // Parse an auth-param, leaving the parser untouched if there isn't
+    // one.
+    fn param(&mut self) -> Option<(&'a [u8], &'a [u8])> {
+        let start = self.rest;
+        let param = self.try_param();
+        if param.is_none() {
+            self.rest = start;
+        }
+
+        param
+    }
+
+    fn try_param(&mut self) -> Option<(&'a [u8], &'a [u8])> {
+        let ows = |c| c == b' ' || c == b'\t';
+
+        let name = self.token()?;
+        self.skip(ows);
+        self.rest = self.rest.strip_prefix(b"=")?;
+        self.skip(ows);
+
+        let value = match self.quoted_string() {
+            Some(value) => value,
+            None => self.token()?,
+        };
+
+        Some((name, value))
+    }
+
+    // Move on to the next auth-param within the same challenge, if there
+    // is one.
+    fn next_param(&mut self) -> bool {
+        let start = self.rest;
+
+        self.skip(|c| c == b' ' || c == b'\t');
+        if self.rest.starts_with(b",") {
+            self.skip(|c| matches!(c, b',' | b' ' | b'\t'));
+
+            // A token that isn't followed by "=" is the scheme of the next
+            // challenge.
+            let next = self.rest;
+            if self.param().is_some() {
+                self.rest = next;
+                return true;
+            }
+        }
+
+        self.rest = start;
+        false
+    }
+}
+
+// Characters allowed unescaped within a quoted-string, excluding DQUOTE.
+// This implementation does not allow for any obsolete syntax.
+//
+// >     qdtext         = HTAB / SP /%x21 / %x23-5B / %x5D-7E / obs-text
+fn is_qdchar(c: u8) -> bool {
+    c == b'\t' || c == b' ' || (c.is_ascii_graphic() && c != b'"')
+}
@@ -0,0 +1,214 @@
+use bytes::BufMut;
+
+use crate::traits::{OutOfBufferError, WriteValue};
+use crate::util::{try_write, validate_etagc};
+#[cfg(feature = "httpdate")]
+use crate::HttpDate;
+use crate::{HeaderValue, Result};
+
+/// An entity tag as used within the `ETag` header and in conditional
+/// requests.
+///
+/// The tag is provided without the surrounding double quotes, they are
+/// added when the value is written out.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::EntityTag;
+/// # fn main() -> Result<(), Error> {
+/// let mut buf = Vec::new();
+/// response::ok(&mut buf)?.header("ETag", EntityTag::weak("xyzzy"))?;
+///
+/// assert_eq!(buf, &b"HTTP/1.1 200 OK\r\nETag: W/\"xyzzy\"\r\n"[..]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EntityTag<'a> {
+    tag: &'a str,
+    weak: bool,
+}
+
+impl<'a> EntityTag<'a> {
+    /// Create a strong entity tag.
+    pub fn strong(tag: &'a str) -> Self {
+        Self { tag, weak: false }
+    }
+
+    /// Create a weak entity tag, written with the `W/` prefix.
+    pub fn weak(tag: &'a str) -> Self {
+        Self { tag, weak: true }
+    }
+
+    /// The opaque tag, without quotes or the weak prefix.
+    pub fn tag(&self) -> &'a str {
+        self.tag
+    }
+
+    /// Whether this is a weak entity tag.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Strong comparison as defined by RFC 7232 section 2.3.2. Both tags
+    /// must be strong and have the same opaque tag.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison as defined by RFC 7232 section 2.3.2. The opaque
+    /// tags must match, whether either is weak is ignored.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        if self.weak {
+            try_write(buf, b"W/")?;
+        }
+
+        try_write(buf, b"\"")?;
+        try_write(buf, self.tag.as_bytes())?;
+        try_write(buf, b"\"")
+    }
+}
+
+impl HeaderValue for EntityTag<'_> {
+    fn put<B: BufMut>(&self, buf: &mut B) -> core::result::Result<(), OutOfBufferError> {
+        if buf.remaining_mut() < self.encoded_len() {
+            return Err(OutOfBufferError);
+        }
+
+        self.write(buf).map_err(|_| OutOfBufferError)
+    }
+
+    fn validate(&self) -> bool {
+        validate_etagc(self.tag.as_bytes())
+    }
+
+    fn est_len(&self) -> Option<usize> {
+        Some(self.encoded_len())
+    }
+
+    fn encoded_len(&self) -> usize {
+        let prefix = if self.weak { 2 } else { 0 };
+
+        prefix + self.tag.len() + 2
+    }
+}
+
+/// The value of an `If-Match` or `If-None-Match` header, either `*` or a
+/// list of entity tags.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::{EntityTag, EntityTags};
+/// # fn main() -> Result<(), Error> {
+/// let tags = [EntityTag::strong("xyzzy"), EntityTag::weak("r2d2xxxx")];
+///
+/// let mut buf = Vec::new();
+/// request::get(&mut buf, Uri::new(b"/"))?
+///     .header("If-None-Match", EntityTags::List(&tags))?
+///     .header("If-Match", EntityTags::Any)?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"GET / HTTP/1.1\r\n\
+///        If-None-Match: \"xyzzy\", W/\"r2d2xxxx\"\r\n\
+///        If-Match: *\r\n"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EntityTags<'a> {
+    /// Matches any current representation, written as `*`.
+    Any,
+    /// A non-empty list of entity tags.
+    List(&'a [EntityTag<'a>]),
+}
+
+impl WriteValue for EntityTags<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        let tags = match *self {
+            EntityTags::Any => return try_write(buf, b"*"),
+            EntityTags::List(tags) => tags,
+        };
+
+        for (i, tag) in tags.iter().enumerate() {
+            if i != 0 {
+                try_write(buf, b", ")?;
+            }
+
+            tag.write(buf)?;
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        match *self {
+            EntityTags::Any => true,
+            EntityTags::List(tags) => !tags.is_empty() && tags.iter().all(|tag| tag.validate()),
+        }
+    }
+}
+
+/// The value of an `If-Range` header, either a strong entity tag or a
+/// date.
+///
+/// The `If-Modified-Since` and `If-Unmodified-Since` headers take an
+/// [`HttpDate`](crate::HttpDate) directly.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IfRange<'a> {
+    /// Only send the range if the representation has this entity tag.
+    ///
+    /// Weak entity tags are not allowed here and fail validation.
+    Tag(EntityTag<'a>),
+    /// Only send the range if the representation has not been modified
+    /// since this date.
+    #[cfg(feature = "httpdate")]
+    Date(HttpDate),
+}
+
+impl HeaderValue for IfRange<'_> {
+    fn put<B: BufMut>(&self, buf: &mut B) -> core::result::Result<(), OutOfBufferError> {
+        match self {
+            IfRange::Tag(tag) => tag.put(buf),
+            #[cfg(feature = "httpdate")]
+            IfRange::Date(date) => date.put(buf),
+        }
+    }
+
+    fn validate(&self) -> bool {
+        match self {
+            IfRange::Tag(tag) => !tag.is_weak() && tag.validate(),
+            #[cfg(feature = "httpdate")]
+            IfRange::Date(date) => date.validate(),
+        }
+    }
+
+    fn put_measured<B: BufMut>(&self, buf: &mut B) -> core::result::Result<(), OutOfBufferError> {
+        match self {
+            IfRange::Tag(tag) => tag.put_measured(buf),
+            #[cfg(feature = "httpdate")]
+            IfRange::Date(date) => date.put_measured(buf),
+        }
+    }
+
+    fn est_len(&self) -> Option<usize> {
+        match self {
+            IfRange::Tag(tag) => tag.est_len(),
+            #[cfg(feature = "httpdate")]
+            IfRange::Date(date) => date.est_len(),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        match self {
+            IfRange::Tag(tag) => tag.encoded_len(),
+            #[cfg(feature = "httpdate")]
+            IfRange::Date(date) => date.encoded_len(),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use bytes::BufMut;
+
+use crate::traits::{OutOfBufferError, WriteValue};
+use crate::util::try_write;
+use crate::{HeaderValue, Result};
+
+/// A single range within a `Range` request header.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ByteRange {
+    /// The bytes from `first` to `last`, inclusive.
+    FromTo(u64, u64),
+    /// All the bytes starting from the given offset.
+    From(u64),
+    /// The given number of bytes at the end of the representation.
+    Last(u64),
+}
+
+impl ByteRange {
+    /// Resolve this range against a representation of `complete_length`
+    /// bytes, returning the first and last byte positions (inclusive).
+    ///
+    /// Returns `None` if the range is not satisfiable.
+    pub fn resolve(self, complete_length: u64) -> Option<(u64, u64)> {
+        if complete_length == 0 {
+            return None;
+        }
+
+        let last_pos = complete_length - 1;
+        match self {
+            ByteRange::FromTo(first, last) if first <= last && first <= last_pos => {
+                Some((first, last.min(last_pos)))
+            }
+            ByteRange::From(first) if first <= last_pos => Some((first, last_pos)),
+            ByteRange::Last(len) if len > 0 => {
+                Some((complete_length.saturating_sub(len), last_pos))
+            }
+            _ => None,
+        }
+    }
+
+    fn write<B: BufMut>(self, buf: &mut B) -> Result<()> {
+        match self {
+            ByteRange::FromTo(first, last) => {
+                first.put(buf)?;
+                try_write(buf, b"-")?;
+                last.put(buf)?;
+            }
+            ByteRange::From(first) => {
+                first.put(buf)?;
+                try_write(buf, b"-")?;
+            }
+            ByteRange::Last(len) => {
+                try_write(buf, b"-")?;
+                len.put(buf)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A `Range` request header value.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::{ByteRange, Range};
+/// # fn main() -> Result<(), Error> {
+/// let mut buf = Vec::new();
+/// let ranges = [ByteRange::FromTo(0, 499), ByteRange::Last(100)];
+/// request::get(&mut buf, Uri::new(b"/video"))?.header("Range", Range::new(&ranges))?;
+///
+/// assert_eq!(buf, &b"GET /video HTTP/1.1\r\nRange: bytes=0-499, -100\r\n"[..]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Range<'a> {
+    ranges: &'a [ByteRange],
+}
+
+impl<'a> Range<'a> {
+    /// Create a byte range set from the provided ranges.
+    pub fn new(ranges: &'a [ByteRange]) -> Self {
+        Self { ranges }
+    }
+}
+
+impl WriteValue for Range<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        try_write(buf, b"bytes=")?;
+
+        for (i, range) in self.ranges.iter().enumerate() {
+            if i != 0 {
+                try_write(buf, b", ")?;
+            }
+
+            range.write(buf)?;
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        !self.ranges.is_empty()
+            && self.ranges.iter().all(|range| match *range {
+                ByteRange::FromTo(first, last) => first <= last,
+                ByteRange::From(_) => true,
+                ByteRange::Last(len) => len > 0,
+            })
+    }
+}
+
+/// A `Content-Range` header value.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::ContentRange;
+/// # fn main() -> Result<(), Error> {
+/// let mut buf = Vec::new();
+/// response::request_range_not_satisfiable(&mut buf)?
+///     .header("Content-Range", ContentRange::Unsatisfied { complete_length: 1234 })?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"HTTP/1.1 416 Requested Range Not Satisfiable\r\nContent-Range: bytes */1234\r\n"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ContentRange {
+    /// The bytes from `first` to `last`, inclusive, out of a
+    /// representation of `complete_length` bytes, if known.
+    Bytes {
+        first: u64,
+        last: u64,
+        complete_length: Option<u64>,
+    },
+    /// None of the requested ranges could be satisfied, as sent within a
+    /// 416 response.
+    Unsatisfied { complete_length: u64 },
+}
+
+impl WriteValue for ContentRange {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        try_write(buf, b"bytes ")?;
+
+        match *self {
+            ContentRange::Bytes {
+                first,
+                last,
+                complete_length,
+            } => {
+                ByteRange::FromTo(first, last).write(buf)?;
+                try_write(buf, b"/")?;
+
+                match complete_length {
+                    Some(len) => len.put(buf)?,
+                    None => try_write(buf, b"*")?,
+                }
+            }
+            ContentRange::Unsatisfied { complete_length } => {
+                try_write(buf, b"*/")?;
+                complete_length.put(buf)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        match *self {
+            ContentRange::Bytes {
+                first,
+                last,
+                complete_length,
+            } => first <= last && complete_length.is_none_or(|len| last < len),
+            ContentRange::Unsatisfied { .. } => true,
+        }
+    }
+}
+
+/// An `Accept-Ranges` header value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AcceptRanges {
+    /// Byte ranges are supported.
+    Bytes,
+    /// No ranges are supported.
+    None,
+}
+
+impl AcceptRanges {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            AcceptRanges::Bytes => b"bytes",
+            AcceptRanges::None => b"none",
+        }
+    }
+}
+
+impl HeaderValue for AcceptRanges {
+    fn put<B: BufMut>(&self, buf: &mut B) -> core::result::Result<(), OutOfBufferError> {
+        self.as_bytes().put(buf)
+    }
+
+    fn est_len(&self) -> Option<usize> {
+        Some(self.as_bytes().len())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.as_bytes().len()
+    }
+}
@@ -0,0 +1,832 @@
+//! HPACK header compression for HTTP/2, as defined in RFC7541.
+//!
+//! The [`Encoder`] keeps its dynamic table inline with a fixed capacity
+//! so that encoding a header block never allocates.
+
+use bytes::BufMut;
+
+use core::fmt;
+
+use crate::huffman;
+use crate::util::{validate_token, Sink};
+use crate::{Error, HeaderValue, OutOfBufferError, Result};
+
+/// How a header field that is not fully indexed should interact with
+/// the dynamic table.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Indexing {
+    /// Add the header field to the dynamic table.
+    Incremental,
+    /// Leave the dynamic table unchanged.
+    Without,
+    /// Leave the dynamic table unchanged and require intermediaries to
+    /// do the same when forwarding the header field. This should be used
+    /// for sensitive values such as cookies and credentials.
+    Never,
+}
+
+/// HPACK encoder with a dynamic table of up to `N` bytes.
+///
+/// Every header block must be encoded in full, and in order, with the
+/// same encoder for the peer's decoder to stay in sync.
+#[derive(Clone)]
+pub struct Encoder<const N: usize = 4096> {
+    table: Table<N>,
+    max_size: usize,
+    // Smallest maximum size set since the last dynamic table size update
+    // was written out, if one is pending.
+    min_size: Option<usize>,
+    huffman: bool,
+}
+
+// A dynamic table of up to `N` bytes, shared by the HPACK and QPACK
+// encoders.
+#[derive(Clone)]
+pub(crate) struct Table<const N: usize> {
+    // Dynamic table entries, oldest first. Each entry is stored as the
+    // name and value lengths as little-endian u32s, followed by the name
+    // and then the value.
+    data: [u8; N],
+    // Number of bytes of `data` in use.
+    used: usize,
+    // Number of entries within the dynamic table.
+    len: usize,
+    // Size of the dynamic table as defined in RFC7541 section 4.1.
+    size: usize,
+}
+
+// Iterator over the entries of a dynamic table, oldest first.
+#[derive(Copy, Clone)]
+pub(crate) struct Entries<'a> {
+    data: &'a [u8],
+}
+
+// Overhead added to the size of each dynamic table entry.
+//
+// > The size of an entry is the sum of its name's length in octets (as
+// > defined in Section 5.2), its value's length in octets, and 32.
+const ENTRY_OVERHEAD: usize = 32;
+
+// Size of the lengths stored in front of each entry in `Table::data`.
+// This is less than `ENTRY_OVERHEAD` so a table of `N` bytes can always
+// hold entries adding up to a size of `N`.
+const RECORD_HEADER: usize = 8;
+
+// The static table as defined in RFC7541 appendix A.
+pub(crate) const STATIC_TABLE: [(&[u8], &[u8]); 61] = [
+    (b":authority", b""),
+    (b":method", b"GET"),
+    (b":method", b"POST"),
+    (b":path", b"/"),
+    (b":path", b"/index.html"),
+    (b":scheme", b"http"),
+    (b":scheme", b"https"),
+    (b":status", b"200"),
+    (b":status", b"204"),
+    (b":status", b"206"),
+    (b":status", b"304"),
+    (b":status", b"400"),
+    (b":status", b"404"),
+    (b":status", b"500"),
+    (b"accept-charset", b""),
+    (b"accept-encoding", b"gzip, deflate"),
+    (b"accept-language", b""),
+    (b"accept-ranges", b""),
+    (b"accept", b""),
+    (b"access-control-allow-origin", b""),
+    (b"age", b""),
+    (b"allow", b""),
+    (b"authorization", b""),
+    (b"cache-control", b""),
+    (b"content-disposition", b""),
+    (b"content-encoding", b""),
+    (b"content-language", b""),
+    (b"content-length", b""),
+    (b"content-location", b""),
+    (b"content-range", b""),
+    (b"content-type", b""),
+    (b"cookie", b""),
+    (b"date", b""),
+    (b"etag", b""),
+    (b"expect", b""),
+    (b"expires", b""),
+    (b"from", b""),
+    (b"host", b""),
+    (b"if-match", b""),
+    (b"if-modified-since", b""),
+    (b"if-none-match", b""),
+    (b"if-range", b""),
+    (b"if-unmodified-since", b""),
+    (b"last-modified", b""),
+    (b"link", b""),
+    (b"location", b""),
+    (b"max-forwards", b""),
+    (b"proxy-authenticate", b""),
+    (b"proxy-authorization", b""),
+    (b"range", b""),
+    (b"referer", b""),
+    (b"refresh", b""),
+    (b"retry-after", b""),
+    (b"server", b""),
+    (b"set-cookie", b""),
+    (b"strict-transport-security", b""),
+    (b"transfer-encoding", b""),
+    (b"user-agent", b""),
+    (b"vary", b""),
+    (b"via", b""),
+    (b"www-authenticate", b""),
+];
+
+enum Match {
+    None,
+    Name(usize),
+    Full(usize),
+}
+
+impl Encoder {
+    /// Create a new encoder with a 4096 byte dynamic table, the default
+    /// size for HTTP/2.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<const N: usize> Encoder<N> {
+    /// Set whether string literals should be Huffman-encoded when doing
+    /// so doesn't make them longer. This is enabled by default.
+    pub fn set_huffman(&mut self, enabled: bool) {
+        self.huffman = enabled;
+    }
+
+    /// Set the maximum size of the dynamic table.
+    ///
+    /// This should be called with the decoder's
+    /// `SETTINGS_HEADER_TABLE_SIZE` whenever it changes. Sizes larger
+    /// than `N` are clamped to `N`.
+    ///
+    /// A dynamic table size update is written out before the next header
+    /// field so this must only be called between header blocks.
+    pub fn set_max_size(&mut self, size: usize) {
+        let size = size.min(N);
+
+        self.table.evict_to(size);
+        self.max_size = size;
+        self.min_size = Some(self.min_size.map_or(size, |min| min.min(size)));
+    }
+
+    /// The maximum size of the dynamic table.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// The current size of the dynamic table, as defined in RFC7541
+    /// section 4.1.
+    pub fn size(&self) -> usize {
+        self.table.size()
+    }
+
+    /// Encode a header field, adding it to the dynamic table if it is not
+    /// already indexed.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer and the dynamic table is unchanged.
+    #[inline]
+    pub fn header<B: BufMut>(
+        &mut self,
+        buf: &mut B,
+        key: impl AsRef<[u8]>,
+        val: impl HeaderValue,
+    ) -> Result<()> {
+        self.header_with_indexing(buf, key, val, Indexing::Incremental)
+    }
+
+    /// Encode a header field that must never be indexed, such as one
+    /// containing credentials.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer and the dynamic table is unchanged.
+    #[inline]
+    pub fn sensitive_header<B: BufMut>(
+        &mut self,
+        buf: &mut B,
+        key: impl AsRef<[u8]>,
+        val: impl HeaderValue,
+    ) -> Result<()> {
+        self.header_with_indexing(buf, key, val, Indexing::Never)
+    }
+
+    /// Encode a header field with the provided indexing behaviour.
+    ///
+    /// Header names are lowercased as they are written out. Pseudo-header
+    /// names starting with `:` are accepted.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer and the dynamic table is unchanged.
+    pub fn header_with_indexing<B: BufMut>(
+        &mut self,
+        buf: &mut B,
+        key: impl AsRef<[u8]>,
+        val: impl HeaderValue,
+        indexing: Indexing,
+    ) -> Result<()> {
+        let key = key.as_ref();
+
+        if !validate_name(key) {
+            return Err(Error::InvalidHeaderKey);
+        }
+        if !val.validate() {
+            return Err(Error::InvalidHeaderValue);
+        }
+
+        let len = val.encoded_len();
+        let found = self.find(key, &val, len, indexing != Indexing::Never);
+
+        // A name index of zero means that the name is a literal.
+        let lower = Lowercase(key);
+        let (index, name) = match found {
+            Match::Full(index) => return self.write_indexed(buf, index),
+            Match::Name(index) => (index, None),
+            Match::None => (0, Some(Literal::new(&lower, key.len(), self.huffman))),
+        };
+        let value = Literal::new(&val, len, self.huffman);
+
+        let (flags, prefix) = match indexing {
+            Indexing::Incremental => (0x40, 6),
+            Indexing::Without => (0x00, 4),
+            Indexing::Never => (0x10, 4),
+        };
+
+        let required = self.update_len()
+            + int_len(index, prefix)
+            + name.as_ref().map_or(0, |name| name.encoded_len(7))
+            + value.encoded_len(7);
+        if buf.remaining_mut() < required {
+            return Err(Error::OutOfBuffer);
+        }
+
+        self.write_update(buf);
+        write_int(buf, flags, prefix, index);
+        if let Some(name) = name {
+            name.write(buf, 0x00, 7)?;
+        }
+        value.write(buf, 0x00, 7)?;
+
+        if indexing == Indexing::Incremental {
+            self.insert(key, &val, len);
+        }
+
+        Ok(())
+    }
+
+    // Write out an indexed header field.
+    fn write_indexed<B: BufMut>(&mut self, buf: &mut B, index: usize) -> Result<()> {
+        if buf.remaining_mut() < self.update_len() + int_len(index, 7) {
+            return Err(Error::OutOfBuffer);
+        }
+
+        self.write_update(buf);
+        write_int(buf, 0x80, 7, index);
+
+        Ok(())
+    }
+
+    // Look up the best index to use for a header field. Exact matches
+    // are only considered if `full` is set.
+    fn find(&self, key: &[u8], val: &impl HeaderValue, len: usize, full: bool) -> Match {
+        let mut found = Match::None;
+
+        for (index, &(name, value)) in STATIC_TABLE.iter().enumerate() {
+            if !name.eq_ignore_ascii_case(key) {
+                continue;
+            }
+
+            if full && value_eq(val, len, value) {
+                return Match::Full(index + 1);
+            }
+            if let Match::None = found {
+                found = Match::Name(index + 1);
+            }
+        }
+
+        // Entries are iterated oldest-first so later matches are
+        // preferred as they have a smaller index.
+        let mut dynamic = Match::None;
+        let first = STATIC_TABLE.len() + self.table.len();
+        for (i, (name, value)) in self.table.entries().enumerate() {
+            let index = first - i;
+
+            if !name.eq_ignore_ascii_case(key) {
+                continue;
+            }
+
+            if full && value_eq(val, len, value) {
+                dynamic = Match::Full(index);
+            } else if let Match::None | Match::Name(_) = dynamic {
+                dynamic = Match::Name(index);
+            }
+        }
+
+        match (found, dynamic) {
+            (_, Match::Full(index)) => Match::Full(index),
+            (Match::None, dynamic) => dynamic,
+            (found, _) => found,
+        }
+    }
+
+    fn insert(&mut self, key: &[u8], val: &impl HeaderValue, len: usize) {
+        let entry_size = entry_size(key.len(), len);
+
+        // > If the size of the new entry is less than or equal to the
+        // > maximum size, that entry is added to the table.  It is not an
+        // > error to attempt to add an entry that is larger than the
+        // > maximum size; an attempt to add an entry larger than the
+        // > maximum size causes the table to be emptied of all existing
+        // > entries and results in an empty table.
+        if entry_size > self.max_size {
+            self.table.evict_to(0);
+            return;
+        }
+        self.table.evict_to(self.max_size - entry_size);
+        self.table.push(key, val, len);
+    }
+
+    fn update_len(&self) -> usize {
+        match self.min_size {
+            Some(min) if min < self.max_size => int_len(min, 5) + int_len(self.max_size, 5),
+            Some(_) => int_len(self.max_size, 5),
+            None => 0,
+        }
+    }
+
+    // Write out any pending dynamic table size update.
+    //
+    // > This dynamic table size update MUST occur at the beginning of the
+    // > first header block following the change to the dynamic table
+    // > size. [...] If the maximum size is reduced and then increased,
+    // > the smallest maximum table size that occurs in that interval MUST
+    // > be signaled in an encoding context update.
+    fn write_update<B: BufMut>(&mut self, buf: &mut B) {
+        if let Some(min) = self.min_size.take() {
+            if min < self.max_size {
+                write_int(buf, 0x20, 5, min);
+            }
+            write_int(buf, 0x20, 5, self.max_size);
+        }
+    }
+}
+
+impl<const N: usize> Default for Encoder<N> {
+    fn default() -> Self {
+        Self {
+            table: Table::new(),
+            max_size: N,
+            min_size: None,
+            huffman: true,
+        }
+    }
+}
+
+impl<const N: usize> fmt::Debug for Encoder<N> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Encoder")
+            .field("len", &self.table.len())
+            .field("size", &self.table.size())
+            .field("max_size", &self.max_size)
+            .field("huffman", &self.huffman)
+            .finish()
+    }
+}
+
+impl<const N: usize> Table<N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: [0; N],
+            used: 0,
+            len: 0,
+            size: 0,
+        }
+    }
+
+    // The number of entries within the table.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    // The size of the table as defined in RFC7541 section 4.1.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn entries(&self) -> Entries<'_> {
+        Entries {
+            data: &self.data[..self.used],
+        }
+    }
+
+    // The number of entries that would have to be evicted for the table
+    // size to be at most `size`.
+    pub(crate) fn evictions(&self, size: usize) -> usize {
+        let mut current = self.size;
+
+        self.entries()
+            .take_while(|(name, value)| {
+                let evict = current > size;
+                current -= entry_size(name.len(), value.len());
+                evict
+            })
+            .count()
+    }
+
+    // Evict the oldest entries until the table size is at most `size`.
+    pub(crate) fn evict_to(&mut self, size: usize) {
+        let mut cut = 0;
+
+        while self.size > size {
+            let ((name, value), _) = split_entry(&self.data[cut..self.used]);
+
+            cut += RECORD_HEADER + name.len() + value.len();
+            self.size -= entry_size(name.len(), value.len());
+            self.len -= 1;
+        }
+
+        self.data.copy_within(cut..self.used, 0);
+        self.used -= cut;
+    }
+
+    // Add a new entry, with the name lowercased. The caller must have
+    // already evicted enough entries for the new entry to fit within a
+    // table size of `N`.
+    pub(crate) fn push(&mut self, key: &[u8], val: &impl HeaderValue, len: usize) {
+        let record = &mut self.data[self.used..];
+        record[..4].copy_from_slice(&(key.len() as u32).to_le_bytes());
+        record[4..RECORD_HEADER].copy_from_slice(&(len as u32).to_le_bytes());
+
+        let mut rest = &mut record[RECORD_HEADER..];
+        let _ = Lowercase(key).put(&mut rest);
+        let _ = val.put(&mut rest);
+
+        self.used += RECORD_HEADER + key.len() + len;
+        self.size += entry_size(key.len(), len);
+        self.len += 1;
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let (entry, rest) = split_entry(self.data);
+        self.data = rest;
+
+        Some(entry)
+    }
+}
+
+// The size of an entry as defined in RFC7541 section 4.1.
+pub(crate) fn entry_size(name_len: usize, value_len: usize) -> usize {
+    name_len + value_len + ENTRY_OVERHEAD
+}
+
+// Split the first entry off of a dynamic table.
+fn split_entry(table: &[u8]) -> ((&[u8], &[u8]), &[u8]) {
+    let name_len = u32::from_le_bytes([table[0], table[1], table[2], table[3]]) as usize;
+    let value_len = u32::from_le_bytes([table[4], table[5], table[6], table[7]]) as usize;
+
+    let (name, rest) = table[RECORD_HEADER..].split_at(name_len);
+    let (value, rest) = rest.split_at(value_len);
+
+    ((name, value), rest)
+}
+
+// Header names may be pseudo-header names, which are a token prefixed
+// with `:`.
+pub(crate) fn validate_name(name: &[u8]) -> bool {
+    match name {
+        [b':', rest @ ..] => validate_token(rest),
+        name => validate_token(name),
+    }
+}
+
+// Header names must be lowercase in HTTP/2 and HTTP/3 so this lowercases
+// the name as it is written out.
+pub(crate) struct Lowercase<'a>(pub(crate) &'a [u8]);
+
+impl HeaderValue for Lowercase<'_> {
+    fn put<B: BufMut>(&self, buf: &mut B) -> core::result::Result<(), OutOfBufferError> {
+        if buf.remaining_mut() < self.0.len() {
+            return Err(OutOfBufferError);
+        }
+
+        for chunk in self.0.chunks(64) {
+            let mut lower = [0u8; 64];
+            let lower = &mut lower[..chunk.len()];
+
+            lower.copy_from_slice(chunk);
+            lower.make_ascii_lowercase();
+            buf.put_slice(lower);
+        }
+
+        Ok(())
+    }
+}
+
+// A string literal along with whether it will be Huffman-encoded.
+pub(crate) struct Literal<'v, V> {
+    value: &'v V,
+    len: usize,
+    huffman: bool,
+}
+
+impl<'v, V: HeaderValue> Literal<'v, V> {
+    // Create a string literal for `value`, which is `len` bytes long.
+    // It is Huffman-encoded if `huffman` is set and doing so doesn't
+    // make it longer, as in the examples in RFC7541 appendix C.
+    pub(crate) fn new(value: &'v V, len: usize, huffman: bool) -> Self {
+        if huffman {
+            let encoded = huffman::encoded_len(value);
+
+            if encoded <= len {
+                return Self {
+                    value,
+                    len: encoded,
+                    huffman: true,
+                };
+            }
+        }
+
+        Self {
+            value,
+            len,
+            huffman: false,
+        }
+    }
+
+    // The length of the literal once written out with a length prefix
+    // of `prefix` bits.
+    pub(crate) fn encoded_len(&self, prefix: u8) -> usize {
+        int_len(self.len, prefix) + self.len
+    }
+
+    // Write out the literal as defined in RFC7541 section 5.2, with a
+    // length prefix of `prefix` bits. The Huffman flag is the bit just
+    // above the prefix.
+    pub(crate) fn write<B: BufMut>(&self, buf: &mut B, flags: u8, prefix: u8) -> Result<()> {
+        if self.huffman {
+            write_int(buf, flags | (1 << prefix), prefix, self.len);
+            huffman::write(buf, self.value);
+        } else {
+            write_int(buf, flags, prefix, self.len);
+            self.value.put_measured(buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+// The length of an integer once written out with a prefix of `prefix`
+// bits, as defined in RFC7541 section 5.1.
+pub(crate) fn int_len(value: usize, prefix: u8) -> usize {
+    let max = (1 << prefix) - 1;
+    if value < max {
+        return 1;
+    }
+
+    let mut value = value - max;
+    let mut len = 2;
+    while value >= 128 {
+        value >>= 7;
+        len += 1;
+    }
+
+    len
+}
+
+// Write out an integer with a prefix of `prefix` bits, as defined in
+// RFC7541 section 5.1. The bits of `flags` above the prefix are written
+// out in the first byte. The caller is responsible for checking that
+// there is enough space.
+//
+// >     if I < 2^N - 1, encode I on N bits
+// >     else
+// >         encode (2^N - 1) on N bits
+// >         I = I - (2^N - 1)
+// >         while I >= 128
+// >              encode (I % 128 + 128) on 8 bits
+// >              I = I / 128
+// >         encode I on 8 bits
+pub(crate) fn write_int<B: BufMut>(buf: &mut B, flags: u8, prefix: u8, value: usize) {
+    let max = (1 << prefix) - 1;
+    if value < max {
+        buf.put_u8(flags | value as u8);
+        return;
+    }
+
+    buf.put_u8(flags | max as u8);
+
+    let mut value = value - max;
+    while value >= 128 {
+        buf.put_u8((value % 128) as u8 | 0x80);
+        value /= 128;
+    }
+    buf.put_u8(value as u8);
+}
+
+// Compare a header value, which is `len` bytes long, against `expected`.
+pub(crate) fn value_eq(val: &impl HeaderValue, len: usize, expected: &[u8]) -> bool {
+    if len != expected.len() {
+        return false;
+    }
+
+    let mut rest = Some(expected);
+    let _ = val.put(&mut Sink::new(|bytes: &[u8]| {
+        rest = match rest {
+            Some(rest) if rest.starts_with(bytes) => Some(&rest[bytes.len()..]),
+            _ => None,
+        };
+    }));
+
+    rest == Some(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // RFC7541 appendix C.3 and C.4
+    #[test]
+    fn rfc_requests() -> Result<()> {
+        for &(huffman, expected) in &[
+            (
+                false,
+                [
+                    "828684410f7777772e6578616d706c652e636f6d",
+                    "828684be58086e6f2d6361636865",
+                    "828785bf400a637573746f6d2d6b65790c637573746f6d2d76616c7565",
+                ],
+            ),
+            (
+                true,
+                [
+                    "828684418cf1e3c2e5f23a6ba0ab90f4ff",
+                    "828684be5886a8eb10649cbf",
+                    "828785bf408825a849e95ba97d7f8925a849e95bb8e8b4bf",
+                ],
+            ),
+        ] {
+            let mut encoder = Encoder::new();
+            encoder.set_huffman(huffman);
+
+            let requests: [&[(&str, &str)]; 3] = [
+                &[
+                    (":method", "GET"),
+                    (":scheme", "http"),
+                    (":path", "/"),
+                    (":authority", "www.example.com"),
+                ],
+                &[
+                    (":method", "GET"),
+                    (":scheme", "http"),
+                    (":path", "/"),
+                    (":authority", "www.example.com"),
+                    ("cache-control", "no-cache"),
+                ],
+                &[
+                    (":method", "GET"),
+                    (":scheme", "https"),
+                    (":path", "/index.html"),
+                    (":authority", "www.example.com"),
+                    ("custom-key", "custom-value"),
+                ],
+            ];
+
+            for (request, expected) in requests.iter().zip(&expected) {
+                let mut buf = Vec::new();
+                for &(key, val) in request.iter() {
+                    encoder.header(&mut buf, key, val)?;
+                }
+
+                assert_eq!(hex(&buf), *expected);
+            }
+
+            assert_eq!(encoder.size(), 164);
+        }
+
+        Ok(())
+    }
+
+    // RFC7541 appendix C.5 and C.6, which exercise eviction.
+    #[test]
+    fn rfc_responses() -> Result<()> {
+        for &(huffman, expected) in &[
+            (
+                false,
+                [
+                    "4803333032580770726976617465611d4d6f6e2c203231204f637420323031332032303a31333a\
+                     323120474d546e1768747470733a2f2f7777772e6578616d706c652e636f6d",
+                    "4803333037c1c0bf",
+                    "88c1611d4d6f6e2c203231204f637420323031332032303a31333a323220474d54c05a04677a69\
+                     707738666f6f3d4153444a4b48514b425a584f5157454f50495541585157454f49553b206d6178\
+                     2d6167653d333630303b2076657273696f6e3d31",
+                ],
+            ),
+            (
+                true,
+                [
+                    "488264025885aec3771a4b6196d07abe941054d444a8200595040b8166e082a62d1bff6e919d29\
+                     ad171863c78f0b97c8e9ae82ae43d3",
+                    "4883640effc1c0bf",
+                    "88c16196d07abe941054d444a8200595040b8166e084a62d1bffc05a839bd9ab77ad94e7821dd7\
+                     f2e6c7b335dfdfcd5b3960d5af27087f3672c1ab270fb5291f9587316065c003ed4ee5b1063d50\
+                     07",
+                ],
+            ),
+        ] {
+            let mut encoder = Encoder::<256>::default();
+            encoder.set_huffman(huffman);
+
+            let responses: [&[(&str, &str)]; 3] = [
+                &[
+                    (":status", "302"),
+                    ("Cache-Control", "private"),
+                    ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                    ("location", "https://www.example.com"),
+                ],
+                &[
+                    (":status", "307"),
+                    ("cache-control", "private"),
+                    ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                    ("location", "https://www.example.com"),
+                ],
+                &[
+                    (":status", "200"),
+                    ("cache-control", "private"),
+                    ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+                    ("location", "https://www.example.com"),
+                    ("content-encoding", "gzip"),
+                    (
+                        "set-cookie",
+                        "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1",
+                    ),
+                ],
+            ];
+
+            let sizes = [222, 222, 215];
+
+            for ((response, expected), size) in responses.iter().zip(&expected).zip(&sizes) {
+                let mut buf = Vec::new();
+                for &(key, val) in response.iter() {
+                    encoder.header(&mut buf, key, val)?;
+                }
+
+                assert_eq!(hex(&buf), *expected);
+                assert_eq!(encoder.size(), *size);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn size_updates_and_sensitive() -> Result<()> {
+        let mut encoder = Encoder::new();
+        encoder.set_huffman(false);
+
+        let mut buf = Vec::new();
+        encoder.header(&mut buf, "x-a", "b")?;
+        assert_eq!(encoder.size(), 36);
+
+        encoder.set_max_size(0);
+        encoder.set_max_size(100);
+        assert_eq!(encoder.size(), 0);
+
+        buf.clear();
+        encoder.sensitive_header(&mut buf, "authorization", "secret")?;
+        assert_eq!(hex(&buf), "203f451f0806736563726574");
+        assert_eq!(encoder.size(), 0);
+
+        let mut small = [0u8; 4];
+        assert_eq!(
+            encoder.header(&mut &mut small[..], "x-long", "value"),
+            Err(Error::OutOfBuffer)
+        );
+        assert_eq!(encoder.size(), 0);
+
+        assert_eq!(
+            encoder.header(&mut buf, "bad key", "value"),
+            Err(Error::InvalidHeaderKey)
+        );
+
+        Ok(())
+    }
+}
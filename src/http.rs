@@ -1,30 +1,58 @@
 use bytes::{Buf, BufMut};
 
+use crate::chunked::Declared;
 use crate::util::{
-    lookup_status_line, validate_header_name, write_request_line, write_status_line,
+    lookup_status_line, put_buf, request_line_max_len, status_line_len, validate_header_name,
+    write_form, write_request_line, write_status_line, Sink,
+};
+use crate::{
+    ChunkedWriter, Counter, Error, HeaderValue, Method, Result, Rewind, Status, Uri, Version,
 };
-use crate::{Error, HeaderValue, Method, Result, Status, Uri, Version};
 
 /// Builder for HTTP requests.
 #[derive(Debug)]
 pub struct HttpBuilder<B: BufMut> {
     buf: B,
+    declared: Declared,
 }
 
 impl<B: BufMut> HttpBuilder<B> {
     /// Create a new request with the provided header line.
     ///
     /// # Note
-    /// If this method fails it may be partially-written into the buffer.
-    /// It is necessary to reset the buffer back externally if that happens.
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
     #[inline]
     pub fn request(mut buf: B, method: Method, version: Version, uri: Uri) -> Result<Self> {
+        // Measuring the request line means escaping the URI twice, so
+        // it's only done when the buffer may not have enough space.
+        if buf.remaining_mut() < request_line_max_len(method, uri, version)? {
+            let mut len = 0;
+            write_request_line(
+                &mut Sink::new(|bytes: &[u8]| len += bytes.len()),
+                method,
+                uri,
+                version,
+            )?;
+
+            if buf.remaining_mut() < len {
+                return Err(Error::OutOfBuffer);
+            }
+        }
+
         write_request_line(&mut buf, method, uri, version)?;
 
-        Ok(Self { buf })
+        Ok(Self {
+            buf,
+            declared: Declared::default(),
+        })
     }
 
     /// Create a new response from the provided status line.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
     #[inline]
     pub fn response(buf: B, version: Version, status: Status) -> Result<Self> {
         Self::response_with_reason(
@@ -36,6 +64,10 @@ impl<B: BufMut> HttpBuilder<B> {
     }
 
     /// Create a new response from the provided status line.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
     #[inline]
     pub fn response_with_reason(
         mut buf: B,
@@ -43,9 +75,16 @@ impl<B: BufMut> HttpBuilder<B> {
         status: Status,
         reason: &str,
     ) -> Result<Self> {
+        if buf.remaining_mut() < status_line_len(version, reason)? {
+            return Err(Error::OutOfBuffer);
+        }
+
         write_status_line(&mut buf, version, status, reason)?;
 
-        Ok(Self { buf })
+        Ok(Self {
+            buf,
+            declared: Declared::default(),
+        })
     }
 
     /// Add a new header to the request. This method does not check
@@ -72,6 +111,11 @@ impl<B: BufMut> HttpBuilder<B> {
     /// Add a new header to the request without checking to ensure that it's
     /// valid.
     ///
+    /// # Safety
+    /// The caller must ensure that the key is a valid header name and that
+    /// the value is a valid header field, otherwise the resulting message
+    /// will be syntactically invalid.
+    ///
     /// # Note
     /// This method is atomic - if it fails then nothing will be written
     /// to the buffer.
@@ -87,16 +131,28 @@ impl<B: BufMut> HttpBuilder<B> {
             return Err(Error::InvalidHeaderKey);
         }
 
-        let est_required = key.len() + val.est_len().unwrap_or(0) + b": \r\n".len();
-        if self.buf.remaining_mut() < est_required {
+        // Trailer fields declared here are checked when the trailer
+        // section of a chunked body is written.
+        let declared = if key.eq_ignore_ascii_case(b"Trailer") {
+            Some(self.declared.declare(&val))
+        } else {
+            None
+        };
+
+        let required = key.len() + val.encoded_len() + b": \r\n".len();
+        if self.buf.remaining_mut() < required {
             return Err(Error::OutOfBuffer);
         }
 
         self.buf.put_slice(key);
         self.buf.put_slice(b": ");
-        val.put(&mut self.buf)?;
+        val.put_measured(&mut self.buf)?;
         self.buf.put_slice(b"\r\n");
 
+        if let Some(declared) = declared {
+            self.declared = declared;
+        }
+
         Ok(self)
     }
 
@@ -121,18 +177,59 @@ impl<B: BufMut> HttpBuilder<B> {
         }
 
         self.buf.put_slice(b"\r\n");
+        put_buf(&mut self.buf, buf);
 
-        while buf.has_remaining() {
-            let bytes = buf.bytes();
-            self.buf.put_slice(bytes);
+        Ok(self.buf)
+    }
+
+    /// Complete the HTTP header with an `application/x-www-form-urlencoded`
+    /// body made up of the given name-value pairs.
+    ///
+    /// This adds the `Content-Type` and `Content-Length` headers. Spaces
+    /// are encoded as `+` and all other bytes outside of the WHATWG
+    /// `application/x-www-form-urlencoded` set are percent-encoded.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn form<I, K, V>(mut self, pairs: I) -> Result<B>
+    where
+        I: IntoIterator<Item = (K, V)> + Clone,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        const CONTENT_TYPE: &[u8] = b"Content-Type: application/x-www-form-urlencoded\r\n";
+
+        let mut counter = Counter::new();
+        write_form(&mut counter, pairs.clone())?;
+        let len = counter.count();
 
-            let len = bytes.len();
-            buf.advance(len);
+        let required =
+            CONTENT_TYPE.len() + b"Content-Length: \r\n\r\n".len() + len.encoded_len() + len;
+        if self.buf.remaining_mut() < required {
+            return Err(Error::OutOfBuffer);
         }
 
+        self.buf.put_slice(CONTENT_TYPE);
+        self.header("Content-Length", len)?;
+        self.buf.put_slice(b"\r\n");
+        write_form(&mut self.buf, pairs)?;
+
         Ok(self.buf)
     }
 
+    /// Complete the HTTP header and start writing a body using the
+    /// chunked transfer coding.
+    ///
+    /// This does not add a `Transfer-Encoding: chunked` header, that
+    /// must be done by the caller. Any fields declared in a `Trailer`
+    /// header are the only ones allowed in the trailer section.
+    pub fn chunked(mut self) -> Result<ChunkedWriter<B>> {
+        let declared = core::mem::take(&mut self.declared);
+
+        self.finish().map(|buf| ChunkedWriter::new(buf, declared))
+    }
+
     /// Get the underlying buffer for this request object.
     pub fn into_buf(self) -> B {
         self.buf
@@ -144,7 +241,10 @@ impl<B: BufMut> HttpBuilder<B> {
     /// This function is unsafe since you can use it to create a syntactically
     /// invalid request.
     pub unsafe fn from_buf(buf: B) -> Self {
-        Self { buf }
+        Self {
+            buf,
+            declared: Declared::default(),
+        }
     }
 
     /// Get the number of remaining bytes within the underlying buffer.
@@ -152,3 +252,26 @@ impl<B: BufMut> HttpBuilder<B> {
         self.buf.remaining_mut()
     }
 }
+
+impl<B: Rewind> HttpBuilder<B> {
+    /// Run a sequence of operations on the builder, such as adding
+    /// several headers, as a single atomic operation.
+    ///
+    /// If `f` fails then the buffer is rewound to where it was before
+    /// `f` was called, along with any trailer fields declared within it.
+    pub fn atomic<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        let checkpoint = self.buf.checkpoint();
+        let declared = self.declared.clone();
+        let result = f(self);
+
+        if result.is_err() {
+            self.buf.rewind(checkpoint);
+            self.declared = declared;
+        }
+
+        result
+    }
+}
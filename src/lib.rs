@@ -1,18 +1,60 @@
 //! Zero-allocation HTTP encoding.
+//!
+//! # Minimum supported Rust version
+//!
+//! This crate requires Rust 1.85 or later, which provides the no-op waker
+//! used to drive the blocking `HttpWriter` and the `dep:` feature syntax
+//! used by the `tokio` and `futures-io` features. The minimum version is
+//! declared as `rust-version` in `Cargo.toml`.
 
 #![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 
+// The `bytes` crate always depends on `alloc` and implements `BufMut`
+// for `Vec<u8>`, so `Rewind` is implemented for it as well.
+extern crate alloc;
+
+mod base64;
+mod chunked;
+mod counter;
 mod error;
+mod hash;
 mod http;
+mod huffman;
+mod rewind;
+#[cfg(feature = "std")]
+mod staging;
 mod traits;
+mod uri;
 mod util;
+#[cfg(feature = "std")]
+mod vectored;
 
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub mod asyncio;
+#[cfg(feature = "std")]
+pub mod blocking;
+pub mod conditional;
+pub mod decode;
+pub mod framed;
+pub mod headers;
+pub mod hpack;
+pub mod multipart;
+pub mod qpack;
 pub mod request;
 pub mod response;
+pub mod strict;
 
+pub use self::chunked::{ChunkedWriter, TrailerWriter};
+pub use self::counter::Counter;
 pub use self::error::Error;
+#[cfg(feature = "std")]
+pub use self::error::IoError;
 pub use self::http::HttpBuilder;
+pub use self::rewind::{Rewind, SliceBuf};
 pub use self::traits::{HeaderValue, OutOfBufferError};
+pub use self::uri::UriBuilder;
+#[cfg(feature = "std")]
+pub use self::vectored::{VectoredBuilder, VectoredMessage};
 
 #[cfg(feature = "httpdate")]
 pub use httpdate::HttpDate;
@@ -21,7 +63,7 @@ pub use httpdate::HttpDate;
 mod tests;
 
 /// HTTP method.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Method<'a> {
     Options,
     Get,
@@ -36,21 +78,22 @@ pub enum Method<'a> {
 }
 
 /// HTTP version.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Version<'a> {
     Http10,
     Http11,
     Custom(&'a str),
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 enum UriData<'a> {
     Escaped(&'a [u8]),
     Unescaped(&'a [u8]),
+    Parts(UriBuilder<'a>),
 }
 
 /// HTTP resource identifier.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct Uri<'a> {
     data: UriData<'a>,
 }
@@ -74,6 +117,11 @@ impl Status {
         Self { code }
     }
 
+    /// Get the numeric status code.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
     /// Get the status line associated with this status code.
     pub fn status_line(&self) -> Option<&'static str> {
         crate::util::lookup_status_line(*self)
@@ -81,22 +129,81 @@ impl Status {
 }
 
 impl<'a> Uri<'a> {
+    /// Create a URI from unescaped bytes. Any characters that are not
+    /// allowed within a request line are percent-encoded.
     pub fn new(uri: &'a [u8]) -> Self {
         Self {
-            data: UriData::Unescaped(uri.as_ref()),
+            data: UriData::Unescaped(uri),
         }
     }
 
+    /// Create a URI from bytes that have already been escaped.
+    ///
+    /// # Safety
+    /// The URI is written out verbatim so it must not contain any
+    /// characters that are invalid within a request line.
     pub unsafe fn escaped_unchecked(uri: &'a [u8]) -> Self {
         Self {
             data: UriData::Escaped(uri),
         }
     }
 
-    pub fn as_bytes(&self) -> &'a [u8] {
+    /// Create an absolute-form request target, such as
+    /// `http://example.com/path?query`, as used for requests to proxies.
+    ///
+    /// The URI must already be escaped, otherwise this fails with
+    /// [`Error::InvalidUri`]. Fragments and userinfo are not allowed.
+    pub fn absolute(uri: &'a [u8]) -> Result<Self> {
+        if !crate::util::is_absolute_form(uri) {
+            return Err(Error::InvalidUri);
+        }
+
+        Ok(Self {
+            data: UriData::Escaped(uri),
+        })
+    }
+
+    /// Create an authority-form request target, such as
+    /// `example.com:443`, as used by `CONNECT` requests.
+    ///
+    /// Fails with [`Error::InvalidUri`] unless the authority is made up of
+    /// a host and a port.
+    pub fn authority(authority: &'a [u8]) -> Result<Self> {
+        if !crate::util::is_authority_form(authority) {
+            return Err(Error::InvalidUri);
+        }
+
+        Ok(Self {
+            data: UriData::Escaped(authority),
+        })
+    }
+
+    /// Create the asterisk-form request target `*`, as used by
+    /// server-wide `OPTIONS` requests.
+    pub fn asterisk() -> Self {
+        Self {
+            data: UriData::Escaped(b"*"),
+        }
+    }
+
+    /// Get the bytes that this URI was created from.
+    ///
+    /// Returns `None` if the URI was created from a [`UriBuilder`], which
+    /// is escaped as it is written out. Use [`parts`](Self::parts) to get
+    /// the builder back instead.
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self.data {
+            UriData::Escaped(s) => Some(s),
+            UriData::Unescaped(s) => Some(s),
+            UriData::Parts(_) => None,
+        }
+    }
+
+    /// Get the builder that this URI was created from, if any.
+    pub fn parts(&self) -> Option<UriBuilder<'a>> {
         match self.data {
-            UriData::Escaped(s) => s,
-            UriData::Unescaped(s) => s,
+            UriData::Parts(parts) => Some(parts),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,665 @@
+//! Encoders for `multipart/form-data` bodies as defined by RFC 7578, and
+//! for `multipart/byteranges` bodies as defined by RFC 7233.
+//!
+//! ```
+//! # use httpencode::*;
+//! # use httpencode::multipart::{Boundary, MultipartWriter, Part};
+//! # fn main() -> Result<(), Error> {
+//! let boundary = Boundary::new("XyZ")?;
+//! let parts = [
+//!     (Part::new("title"), 5),
+//!     (Part::new("file").filename("a.txt").content_type("text/plain"), 3),
+//! ];
+//!
+//! let mut buf = Vec::new();
+//! let req = request::post(&mut buf, Uri::new(b"/upload"))?;
+//! let mut writer = MultipartWriter::sized(req, &boundary, &parts)?;
+//! writer.part(&parts[0].0)?.write(&mut &b"hello"[..])?;
+//! writer.part(&parts[1].0)?.write(&mut &b"abc"[..])?;
+//! writer.finish()?;
+//!
+//! assert_eq!(
+//!     buf,
+//!     &b"POST /upload HTTP/1.1\r\n\
+//!        Content-Type: multipart/form-data; boundary=\"XyZ\"\r\n\
+//!        Content-Length: 174\r\n\
+//!        \r\n\
+//!        --XyZ\r\n\
+//!        Content-Disposition: form-data; name=\"title\"\r\n\
+//!        \r\n\
+//!        hello\r\n\
+//!        --XyZ\r\n\
+//!        Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+//!        Content-Type: text/plain\r\n\
+//!        \r\n\
+//!        abc\r\n\
+//!        --XyZ--\r\n"[..]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use arrayvec::ArrayVec;
+use bytes::{Buf, BufMut};
+
+use crate::headers::ContentRange;
+use crate::traits::{OutOfBufferError, WriteValue};
+use crate::util::{put_buf, quoted_string_len, try_write, write_percent_escaped, write_quoted};
+use crate::{Counter, Error, HeaderValue, HttpBuilder, Result};
+
+const MAX_BOUNDARY_LEN: usize = 70;
+
+/// The delimiter that separates the parts of a multipart body.
+///
+/// The boundary must not appear anywhere within the body of a part.
+#[derive(Clone, Debug)]
+pub struct Boundary {
+    data: ArrayVec<[u8; MAX_BOUNDARY_LEN]>,
+}
+
+impl Boundary {
+    /// Create a boundary from the provided string.
+    ///
+    /// Fails with [`Error::InvalidBoundary`] if the boundary is empty,
+    /// longer than 70 characters, ends with a space, or contains characters
+    /// that are not allowed by RFC 2046.
+    pub fn new(boundary: &str) -> Result<Self> {
+        let bytes = boundary.as_bytes();
+
+        if bytes.is_empty() || bytes.len() > MAX_BOUNDARY_LEN || bytes.ends_with(b" ") {
+            return Err(Error::InvalidBoundary);
+        }
+        if !bytes.iter().all(|&c| is_bchar(c)) {
+            return Err(Error::InvalidBoundary);
+        }
+
+        let mut data = ArrayVec::new();
+        data.try_extend_from_slice(bytes)
+            .map_err(|_| Error::InvalidBoundary)?;
+
+        Ok(Self { data })
+    }
+
+    /// Generate a random boundary.
+    ///
+    /// The randomness comes from the standard library's hash map seeds, it
+    /// is unique enough to not collide with typical bodies but is not
+    /// cryptographically secure.
+    #[cfg(feature = "std")]
+    pub fn random() -> Self {
+        use std::collections::hash_map::RandomState;
+        use std::hash::{BuildHasher, Hasher};
+
+        const PREFIX: &[u8] = b"httpencode-";
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+
+        let mut data = ArrayVec::new();
+        data.try_extend_from_slice(PREFIX).unwrap();
+
+        for _ in 0..2 {
+            let mut hash = RandomState::new().build_hasher().finish();
+
+            for _ in 0..16 {
+                data.push(HEX[(hash & 0xF) as usize]);
+                hash >>= 4;
+            }
+        }
+
+        Self { data }
+    }
+
+    /// Get the boundary as a string.
+    pub fn as_str(&self) -> &str {
+        // The constructors only ever allow ASCII characters.
+        core::str::from_utf8(&self.data).unwrap()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+// bchars as defined in RFC 2046 section 5.1.1.
+fn is_bchar(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric()
+        || matches!(
+            byte,
+            b'\''
+                | b'('
+                | b')'
+                | b'+'
+                | b'_'
+                | b','
+                | b'-'
+                | b'.'
+                | b'/'
+                | b':'
+                | b'='
+                | b'?'
+                | b' '
+        )
+}
+
+/// The headers of a single part within a multipart body.
+#[derive(Copy, Clone, Debug)]
+pub struct Part<'a> {
+    name: &'a str,
+    filename: Option<&'a str>,
+    content_type: Option<&'a str>,
+}
+
+impl<'a> Part<'a> {
+    /// Create a part for the form field `name`.
+    pub fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            filename: None,
+            content_type: None,
+        }
+    }
+
+    /// Set the name of the file that the contents of this part came from.
+    ///
+    /// Non-ASCII filenames are also written using the RFC 5987 extended
+    /// notation (`filename*`), along with an ASCII fallback.
+    pub fn filename(mut self, filename: &'a str) -> Self {
+        self.filename = Some(filename);
+        self
+    }
+
+    /// Set the `Content-Type` of this part.
+    pub fn content_type(mut self, content_type: &'a str) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    fn write_header<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        // Safety: The headers are validated by `HttpBuilder::header`.
+        let mut builder = unsafe { HttpBuilder::from_buf(buf) };
+
+        builder.header("Content-Disposition", Disposition(self))?;
+        if let Some(content_type) = self.content_type {
+            builder.header("Content-Type", content_type)?;
+        }
+
+        builder.finish()?;
+        Ok(())
+    }
+}
+
+// The value of the `Content-Disposition` header for a part.
+struct Disposition<'a>(&'a Part<'a>);
+
+// attr-char as defined in RFC 5987 section 3.2.1.
+fn is_attr_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric()
+        || matches!(
+            byte,
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~'
+        )
+}
+
+impl WriteValue for Disposition<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        let part = self.0;
+
+        try_write(buf, b"form-data; name=")?;
+        write_quoted(buf, part.name.as_bytes())?;
+
+        if let Some(filename) = part.filename {
+            try_write(buf, b"; filename=")?;
+
+            if filename.is_ascii() {
+                write_quoted(buf, filename.as_bytes())?;
+            } else {
+                // Replace every non-ASCII character with an underscore for
+                // recipients that don't support the extended notation.
+                try_write(buf, b"\"")?;
+                for c in filename.chars() {
+                    let c = if c.is_ascii() { c as u8 } else { b'_' };
+                    if c == b'"' || c == b'\\' {
+                        try_write(buf, b"\\")?;
+                    }
+                    try_write(buf, &[c])?;
+                }
+                try_write(buf, b"\"")?;
+
+                try_write(buf, b"; filename*=UTF-8''")?;
+                write_percent_escaped(buf, filename.as_bytes(), is_attr_char)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        let part = self.0;
+
+        quoted_string_len(part.name.as_bytes()).is_some()
+            && part.filename.is_none_or(|filename| {
+                filename.chars().all(|c| !c.is_ascii_control())
+                    && (!filename.is_ascii() || quoted_string_len(filename.as_bytes()).is_some())
+            })
+    }
+}
+
+/// Calculate the length of a multipart body made up of the given parts,
+/// along with the length of their contents.
+pub fn content_length(boundary: &Boundary, parts: &[(Part<'_>, usize)]) -> Result<usize> {
+    let mut counter = Counter::new();
+
+    for (i, (part, _)) in parts.iter().enumerate() {
+        write_delimiter(&mut counter, boundary, i == 0)?;
+        part.write_header(&mut counter)?;
+    }
+    write_close_delimiter(&mut counter, boundary, parts.is_empty())?;
+
+    Ok(parts.iter().map(|(_, len)| len).sum::<usize>() + counter.count())
+}
+
+fn write_delimiter<B: BufMut>(buf: &mut B, boundary: &Boundary, first: bool) -> Result<()> {
+    if !first {
+        try_write(buf, b"\r\n")?;
+    }
+
+    try_write(buf, b"--")?;
+    try_write(buf, boundary.as_bytes())?;
+    try_write(buf, b"\r\n")
+}
+
+fn write_close_delimiter<B: BufMut>(buf: &mut B, boundary: &Boundary, first: bool) -> Result<()> {
+    if !first {
+        try_write(buf, b"\r\n")?;
+    }
+
+    try_write(buf, b"--")?;
+    try_write(buf, boundary.as_bytes())?;
+    try_write(buf, b"--\r\n")
+}
+
+/// Writer for a `multipart/form-data` body.
+///
+/// Each part is started with [`part`](Self::part) after which its
+/// contents can be written with one or more calls to
+/// [`write`](Self::write).
+#[derive(Debug)]
+pub struct MultipartWriter<'b, B: BufMut> {
+    buf: B,
+    boundary: &'b Boundary,
+    parts: usize,
+    written: usize,
+    expected: Option<usize>,
+}
+
+impl<'b, B: BufMut> MultipartWriter<'b, B> {
+    /// Add the `Content-Type` header, complete the HTTP header, and start
+    /// writing a multipart body.
+    ///
+    /// This does not add a `Content-Length` header, use
+    /// [`sized`](Self::sized) if the length of all the parts is known up
+    /// front.
+    pub fn new(mut builder: HttpBuilder<B>, boundary: &'b Boundary) -> Result<Self> {
+        builder.header(
+            "Content-Type",
+            ContentType {
+                media_type: b"multipart/form-data",
+                boundary,
+            },
+        )?;
+
+        Ok(Self {
+            buf: builder.finish()?,
+            boundary,
+            parts: 0,
+            written: 0,
+            expected: None,
+        })
+    }
+
+    /// Add the `Content-Type` and `Content-Length` headers, complete the
+    /// HTTP header, and start writing a multipart body made up of `parts`.
+    ///
+    /// The parts must then be written in the same order with exactly the
+    /// given lengths, otherwise writing the body will fail with
+    /// [`Error::BodyLengthMismatch`].
+    pub fn sized(
+        mut builder: HttpBuilder<B>,
+        boundary: &'b Boundary,
+        parts: &[(Part<'_>, usize)],
+    ) -> Result<Self> {
+        let length = content_length(boundary, parts)?;
+
+        builder.header(
+            "Content-Type",
+            ContentType {
+                media_type: b"multipart/form-data",
+                boundary,
+            },
+        )?;
+        builder.header("Content-Length", length)?;
+
+        Ok(Self {
+            buf: builder.finish()?,
+            boundary,
+            parts: 0,
+            written: 0,
+            expected: Some(length),
+        })
+    }
+
+    // Check that `len` more bytes can be written out and account for them.
+    fn reserve(&mut self, len: usize) -> Result<()> {
+        if self.buf.remaining_mut() < len {
+            return Err(Error::OutOfBuffer);
+        }
+        if self
+            .expected
+            .is_some_and(|expected| self.written + len > expected)
+        {
+            return Err(Error::BodyLengthMismatch);
+        }
+
+        self.written += len;
+        Ok(())
+    }
+
+    /// Start a new part.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn part(&mut self, part: &Part<'_>) -> Result<&mut Self> {
+        let first = self.parts == 0;
+
+        let mut counter = Counter::new();
+        write_delimiter(&mut counter, self.boundary, first)?;
+        part.write_header(&mut counter)?;
+        self.reserve(counter.count())?;
+
+        write_delimiter(&mut self.buf, self.boundary, first)?;
+        part.write_header(&mut self.buf)?;
+        self.parts += 1;
+
+        Ok(self)
+    }
+
+    /// Write some of the contents of the current part.
+    ///
+    /// Fails with [`Error::BodyNotAllowed`] if no part has been started.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn write<I: Buf>(&mut self, data: &mut I) -> Result<&mut Self> {
+        if self.parts == 0 {
+            return Err(Error::BodyNotAllowed);
+        }
+
+        self.reserve(data.remaining())?;
+        put_buf(&mut self.buf, data);
+
+        Ok(self)
+    }
+
+    /// Write the closing delimiter and return the underlying buffer.
+    pub fn finish(mut self) -> Result<B> {
+        let first = self.parts == 0;
+
+        let mut counter = Counter::new();
+        write_close_delimiter(&mut counter, self.boundary, first)?;
+        self.reserve(counter.count())?;
+        if self
+            .expected
+            .is_some_and(|expected| self.written != expected)
+        {
+            return Err(Error::BodyLengthMismatch);
+        }
+
+        write_close_delimiter(&mut self.buf, self.boundary, first)?;
+        Ok(self.buf)
+    }
+
+    /// Get the underlying buffer.
+    pub fn into_buf(self) -> B {
+        self.buf
+    }
+
+    /// Get the number of remaining bytes within the underlying buffer.
+    pub fn remaining(&self) -> usize {
+        self.buf.remaining_mut()
+    }
+}
+
+// The value of the `Content-Type` header for a multipart body.
+struct ContentType<'a> {
+    media_type: &'static [u8],
+    boundary: &'a Boundary,
+}
+
+impl HeaderValue for ContentType<'_> {
+    fn put<B: BufMut>(&self, buf: &mut B) -> core::result::Result<(), OutOfBufferError> {
+        if buf.remaining_mut() < self.encoded_len() {
+            return Err(OutOfBufferError);
+        }
+
+        buf.put_slice(self.media_type);
+        buf.put_slice(b"; boundary=\"");
+        buf.put_slice(self.boundary.as_bytes());
+        buf.put_slice(b"\"");
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.media_type.len() + b"; boundary=\"\"".len() + self.boundary.as_bytes().len()
+    }
+}
+
+/// Writer for the body of a 206 (Partial Content) response.
+///
+/// A single range is sent as the body with a `Content-Range` header,
+/// multiple ranges are sent as a `multipart/byteranges` body. Either way
+/// the exact `Content-Length` is written out up front.
+///
+/// ```
+/// # use httpencode::*;
+/// # use httpencode::headers::ByteRange;
+/// # use httpencode::multipart::{Boundary, ByteRangesWriter};
+/// # fn main() -> Result<(), Error> {
+/// let body = b"0123456789";
+/// let ranges = [ByteRange::FromTo(0, 1).resolve(10).unwrap()];
+///
+/// let mut buf = Vec::new();
+/// let res = response::partial_content(&mut buf)?;
+/// let boundary = Boundary::new("XyZ")?;
+/// let mut writer = ByteRangesWriter::new(res, &boundary, Some("text/plain"), &ranges, 10)?;
+/// for &(first, last) in &ranges {
+///     writer.range(&mut &body[first as usize..=last as usize])?;
+/// }
+/// writer.finish()?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"HTTP/1.1 206 Partial Content\r\n\
+///        Content-Type: text/plain\r\n\
+///        Content-Range: bytes 0-1/10\r\n\
+///        Content-Length: 2\r\n\
+///        \r\n\
+///        01"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ByteRangesWriter<'a, B: BufMut> {
+    buf: B,
+    ranges: Ranges<'a>,
+    index: usize,
+}
+
+impl<'a, B: BufMut> ByteRangesWriter<'a, B> {
+    /// Add the headers describing the ranges, complete the HTTP header,
+    /// and start writing the body.
+    ///
+    /// `ranges` are the first and last byte positions, inclusive, of each
+    /// range within a representation of `complete_length` bytes, see
+    /// [`ByteRange::resolve`](crate::headers::ByteRange::resolve). The
+    /// boundary is only used if there is more than one range.
+    ///
+    /// Fails with [`Error::UnsatisfiableRange`] if there are no ranges or
+    /// if any of them lie outside of the representation.
+    pub fn new(
+        mut builder: HttpBuilder<B>,
+        boundary: &'a Boundary,
+        content_type: Option<&'a str>,
+        ranges: &'a [(u64, u64)],
+        complete_length: u64,
+    ) -> Result<Self> {
+        if ranges.is_empty()
+            || ranges
+                .iter()
+                .any(|&(first, last)| first > last || last >= complete_length)
+        {
+            return Err(Error::UnsatisfiableRange);
+        }
+
+        let ranges = Ranges {
+            boundary,
+            content_type,
+            ranges,
+            complete_length,
+        };
+        let mut length = ranges
+            .ranges
+            .iter()
+            .map(|(first, last)| last - first + 1)
+            .sum::<u64>();
+
+        if ranges.is_multipart() {
+            let mut counter = Counter::new();
+            for index in 0..ranges.ranges.len() {
+                ranges.write_part_header(&mut counter, index)?;
+            }
+            write_close_delimiter(&mut counter, boundary, false)?;
+            length += counter.count() as u64;
+
+            builder.header(
+                "Content-Type",
+                ContentType {
+                    media_type: b"multipart/byteranges",
+                    boundary,
+                },
+            )?;
+        } else {
+            if let Some(content_type) = content_type {
+                builder.header("Content-Type", content_type)?;
+            }
+            builder.header("Content-Range", ranges.content_range(0))?;
+        }
+        builder.header("Content-Length", length)?;
+
+        Ok(Self {
+            buf: builder.finish()?,
+            ranges,
+            index: 0,
+        })
+    }
+
+    /// Write out the contents of the next range, `data` must be exactly as
+    /// long as the range.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn range<I: Buf>(&mut self, data: &mut I) -> Result<&mut Self> {
+        let (first, last) = match self.ranges.ranges.get(self.index) {
+            Some(&range) => range,
+            None => return Err(Error::BodyLengthMismatch),
+        };
+        if data.remaining() as u64 != last - first + 1 {
+            return Err(Error::BodyLengthMismatch);
+        }
+
+        let mut counter = Counter::new();
+        self.ranges.write_part_header(&mut counter, self.index)?;
+        if self.buf.remaining_mut() < counter.count() + data.remaining() {
+            return Err(Error::OutOfBuffer);
+        }
+
+        self.ranges.write_part_header(&mut self.buf, self.index)?;
+        put_buf(&mut self.buf, data);
+        self.index += 1;
+
+        Ok(self)
+    }
+
+    /// Complete the body and return the underlying buffer.
+    ///
+    /// Fails with [`Error::BodyLengthMismatch`] if not all of the ranges
+    /// have been written.
+    pub fn finish(mut self) -> Result<B> {
+        if self.index != self.ranges.ranges.len() {
+            return Err(Error::BodyLengthMismatch);
+        }
+        if self.ranges.is_multipart() {
+            write_close_delimiter(&mut self.buf, self.ranges.boundary, false)?;
+        }
+
+        Ok(self.buf)
+    }
+
+    /// Get the underlying buffer.
+    pub fn into_buf(self) -> B {
+        self.buf
+    }
+
+    /// Get the number of remaining bytes within the underlying buffer.
+    pub fn remaining(&self) -> usize {
+        self.buf.remaining_mut()
+    }
+}
+
+// The ranges written out by a `ByteRangesWriter`.
+#[derive(Debug)]
+struct Ranges<'a> {
+    boundary: &'a Boundary,
+    content_type: Option<&'a str>,
+    ranges: &'a [(u64, u64)],
+    complete_length: u64,
+}
+
+impl Ranges<'_> {
+    fn is_multipart(&self) -> bool {
+        self.ranges.len() > 1
+    }
+
+    fn content_range(&self, index: usize) -> ContentRange {
+        let (first, last) = self.ranges[index];
+
+        ContentRange::Bytes {
+            first,
+            last,
+            complete_length: Some(self.complete_length),
+        }
+    }
+
+    // Write the delimiter and headers that precede a range within a
+    // multipart body. Nothing is written for single part bodies.
+    fn write_part_header<B: BufMut>(&self, buf: &mut B, index: usize) -> Result<()> {
+        if !self.is_multipart() {
+            return Ok(());
+        }
+
+        write_delimiter(buf, self.boundary, index == 0)?;
+
+        // Safety: The headers are validated by `HttpBuilder::header`.
+        let mut builder = unsafe { HttpBuilder::from_buf(buf) };
+        if let Some(content_type) = self.content_type {
+            builder.header("Content-Type", content_type)?;
+        }
+        builder.header("Content-Range", self.content_range(index))?;
+        builder.finish()?;
+
+        Ok(())
+    }
+}
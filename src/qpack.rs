@@ -0,0 +1,886 @@
+//! QPACK field compression for HTTP/3, as defined in RFC9204.
+//!
+//! The [`Encoder`] only uses the static table until the dynamic table is
+//! enabled with [`Encoder::set_capacity`]. Dynamic table entries are only
+//! referenced once the decoder has acknowledged them so encoded field
+//! sections never block the decoder.
+
+use arrayvec::ArrayVec;
+use bytes::BufMut;
+
+use core::fmt;
+use core::ops::Range;
+
+use crate::hpack::{
+    entry_size, int_len, validate_name, value_eq, write_int, Entries, Literal, Lowercase, Table,
+};
+use crate::util::{method_bytes, Sink};
+use crate::{Error, HeaderValue, Method, Result, Status, Uri};
+
+/// QPACK encoder with a dynamic table of up to `N` bytes.
+///
+/// Encoder instructions are written to the encoder stream buffer passed
+/// to [`set_capacity`](Self::set_capacity) and [`insert`](Self::insert)
+/// and must be sent to the peer in order. Everything received on the
+/// peer's decoder stream must be passed to
+/// [`decoder_instructions`](Self::decoder_instructions).
+#[derive(Clone)]
+pub struct Encoder<const N: usize = 4096> {
+    table: Table<N>,
+    capacity: usize,
+    // The decoder's SETTINGS_QPACK_MAX_TABLE_CAPACITY.
+    max_capacity: usize,
+    // Total number of entries ever inserted into the dynamic table.
+    inserted: u64,
+    // Number of inserts that the decoder has acknowledged.
+    known_received: u64,
+    // Smallest absolute index referenced by an unacknowledged insert
+    // instruction, along with the insert count that acknowledges it.
+    pending_ref: Option<(u64, u64)>,
+    // Field sections referencing the dynamic table that have not been
+    // acknowledged yet.
+    sections: ArrayVec<[Section; MAX_SECTIONS]>,
+    huffman: bool,
+}
+
+/// A field section being encoded by [`Encoder::encode`].
+pub struct FieldSection<'a> {
+    out: &'a mut dyn BufMut,
+    entries: Entries<'a>,
+    // Absolute index of the oldest dynamic table entry.
+    first: u64,
+    // The base of the field section. Only entries before the base are
+    // referenced.
+    base: u64,
+    huffman: bool,
+    // Smallest and largest absolute indices referenced so far.
+    refs: Option<(u64, u64)>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Section {
+    stream_id: u64,
+    required_insert_count: u64,
+    // Smallest absolute index referenced by the field section.
+    min_ref: u64,
+}
+
+// Maximum number of unacknowledged field sections that may reference
+// the dynamic table. Any further field sections only use the static
+// table.
+const MAX_SECTIONS: usize = 16;
+
+enum Index {
+    Static(usize),
+    // An absolute index as defined in RFC9204 section 3.2.4.
+    Dynamic(u64),
+}
+
+enum Match {
+    None,
+    Name(Index),
+    Full(Index),
+}
+
+// The static table as defined in RFC9204 appendix A.
+const STATIC_TABLE: [(&[u8], &[u8]); 99] = [
+    (b":authority", b""),
+    (b":path", b"/"),
+    (b"age", b"0"),
+    (b"content-disposition", b""),
+    (b"content-length", b"0"),
+    (b"cookie", b""),
+    (b"date", b""),
+    (b"etag", b""),
+    (b"if-modified-since", b""),
+    (b"if-none-match", b""),
+    (b"last-modified", b""),
+    (b"link", b""),
+    (b"location", b""),
+    (b"referer", b""),
+    (b"set-cookie", b""),
+    (b":method", b"CONNECT"),
+    (b":method", b"DELETE"),
+    (b":method", b"GET"),
+    (b":method", b"HEAD"),
+    (b":method", b"OPTIONS"),
+    (b":method", b"POST"),
+    (b":method", b"PUT"),
+    (b":scheme", b"http"),
+    (b":scheme", b"https"),
+    (b":status", b"103"),
+    (b":status", b"200"),
+    (b":status", b"304"),
+    (b":status", b"404"),
+    (b":status", b"503"),
+    (b"accept", b"*/*"),
+    (b"accept", b"application/dns-message"),
+    (b"accept-encoding", b"gzip, deflate, br"),
+    (b"accept-ranges", b"bytes"),
+    (b"access-control-allow-headers", b"cache-control"),
+    (b"access-control-allow-headers", b"content-type"),
+    (b"access-control-allow-origin", b"*"),
+    (b"cache-control", b"max-age=0"),
+    (b"cache-control", b"max-age=2592000"),
+    (b"cache-control", b"max-age=604800"),
+    (b"cache-control", b"no-cache"),
+    (b"cache-control", b"no-store"),
+    (b"cache-control", b"public, max-age=31536000"),
+    (b"content-encoding", b"br"),
+    (b"content-encoding", b"gzip"),
+    (b"content-type", b"application/dns-message"),
+    (b"content-type", b"application/javascript"),
+    (b"content-type", b"application/json"),
+    (b"content-type", b"application/x-www-form-urlencoded"),
+    (b"content-type", b"image/gif"),
+    (b"content-type", b"image/jpeg"),
+    (b"content-type", b"image/png"),
+    (b"content-type", b"text/css"),
+    (b"content-type", b"text/html; charset=utf-8"),
+    (b"content-type", b"text/plain"),
+    (b"content-type", b"text/plain;charset=utf-8"),
+    (b"range", b"bytes=0-"),
+    (b"strict-transport-security", b"max-age=31536000"),
+    (
+        b"strict-transport-security",
+        b"max-age=31536000; includesubdomains",
+    ),
+    (
+        b"strict-transport-security",
+        b"max-age=31536000; includesubdomains; preload",
+    ),
+    (b"vary", b"accept-encoding"),
+    (b"vary", b"origin"),
+    (b"x-content-type-options", b"nosniff"),
+    (b"x-xss-protection", b"1; mode=block"),
+    (b":status", b"100"),
+    (b":status", b"204"),
+    (b":status", b"206"),
+    (b":status", b"302"),
+    (b":status", b"400"),
+    (b":status", b"403"),
+    (b":status", b"421"),
+    (b":status", b"425"),
+    (b":status", b"500"),
+    (b"accept-language", b""),
+    (b"access-control-allow-credentials", b"FALSE"),
+    (b"access-control-allow-credentials", b"TRUE"),
+    (b"access-control-allow-headers", b"*"),
+    (b"access-control-allow-methods", b"get"),
+    (b"access-control-allow-methods", b"get, post, options"),
+    (b"access-control-allow-methods", b"options"),
+    (b"access-control-expose-headers", b"content-length"),
+    (b"access-control-request-headers", b"content-type"),
+    (b"access-control-request-method", b"get"),
+    (b"access-control-request-method", b"post"),
+    (b"alt-svc", b"clear"),
+    (b"authorization", b""),
+    (
+        b"content-security-policy",
+        b"script-src 'none'; object-src 'none'; base-uri 'none'",
+    ),
+    (b"early-data", b"1"),
+    (b"expect-ct", b""),
+    (b"forwarded", b""),
+    (b"if-range", b""),
+    (b"origin", b""),
+    (b"purpose", b"prefetch"),
+    (b"server", b""),
+    (b"timing-allow-origin", b"*"),
+    (b"upgrade-insecure-requests", b"1"),
+    (b"user-agent", b""),
+    (b"x-forwarded-for", b""),
+    (b"x-frame-options", b"deny"),
+    (b"x-frame-options", b"sameorigin"),
+];
+
+impl Encoder {
+    /// Create a new encoder with a dynamic table of up to 4096 bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<const N: usize> Encoder<N> {
+    /// Set whether string literals should be Huffman-encoded when doing
+    /// so doesn't make them longer. This is enabled by default.
+    pub fn set_huffman(&mut self, enabled: bool) {
+        self.huffman = enabled;
+    }
+
+    /// Set the maximum capacity of the dynamic table.
+    ///
+    /// This should be called with the decoder's
+    /// `SETTINGS_QPACK_MAX_TABLE_CAPACITY` before the dynamic table is
+    /// used. Field sections encode their Required Insert Count relative
+    /// to this value, so it can't be changed once the dynamic table has
+    /// been enabled.
+    pub fn set_max_capacity(&mut self, capacity: usize) -> Result<()> {
+        let in_use = self.capacity != 0 || self.inserted != 0;
+        if in_use && capacity != self.max_capacity {
+            return Err(Error::InvalidTableCapacity);
+        }
+
+        self.max_capacity = capacity;
+
+        Ok(())
+    }
+
+    /// Set the capacity of the dynamic table, writing a Set Dynamic Table
+    /// Capacity instruction to the encoder stream.
+    ///
+    /// The capacity may not exceed `N` or the maximum capacity allowed by
+    /// the decoder.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer and the dynamic table is unchanged.
+    pub fn set_capacity<B: BufMut>(&mut self, stream: &mut B, capacity: usize) -> Result<()> {
+        if capacity > N.min(self.max_capacity) {
+            return Err(Error::InvalidTableCapacity);
+        }
+        if !self.can_evict(self.table.evictions(capacity)) {
+            return Err(Error::TableFull);
+        }
+        if stream.remaining_mut() < int_len(capacity, 5) {
+            return Err(Error::OutOfBuffer);
+        }
+
+        write_int(stream, 0x20, 5, capacity);
+        self.table.evict_to(capacity);
+        self.capacity = capacity;
+
+        Ok(())
+    }
+
+    /// The capacity of the dynamic table.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The current size of the dynamic table, as defined in RFC9204
+    /// section 3.2.1.
+    pub fn size(&self) -> usize {
+        self.table.size()
+    }
+
+    /// Insert a field into the dynamic table, writing the instruction to
+    /// the encoder stream.
+    ///
+    /// Field sections only reference the new entry once the decoder has
+    /// acknowledged it. This fails with [`Error::TableFull`] if making
+    /// room for the entry would evict an entry that is still in use.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer and the dynamic table is unchanged.
+    pub fn insert<B: BufMut>(
+        &mut self,
+        stream: &mut B,
+        key: impl AsRef<[u8]>,
+        val: impl HeaderValue,
+    ) -> Result<()> {
+        let key = key.as_ref();
+
+        if !validate_name(key) {
+            return Err(Error::InvalidHeaderKey);
+        }
+        if !val.validate() {
+            return Err(Error::InvalidHeaderValue);
+        }
+
+        let len = val.encoded_len();
+        let size = entry_size(key.len(), len);
+        if size > self.capacity {
+            return Err(Error::TableFull);
+        }
+
+        let evictions = self.table.evictions(self.capacity - size);
+        if !self.can_evict(evictions) {
+            return Err(Error::TableFull);
+        }
+
+        // Entries evicted by this insert cannot be referenced by it.
+        let usable = self.first() + evictions as u64..self.inserted;
+        let found = find(
+            key,
+            &val,
+            len,
+            true,
+            self.table.entries(),
+            self.first(),
+            usable,
+        );
+
+        let lower = Lowercase(key);
+        let name = Literal::new(&lower, key.len(), self.huffman);
+        let value = Literal::new(&val, len, self.huffman);
+
+        let required = match found {
+            Match::Full(Index::Dynamic(abs)) => int_len(self.relative(abs), 5),
+            Match::Full(Index::Static(index)) | Match::Name(Index::Static(index)) => {
+                int_len(index, 6) + value.encoded_len(7)
+            }
+            Match::Name(Index::Dynamic(abs)) => {
+                int_len(self.relative(abs), 6) + value.encoded_len(7)
+            }
+            Match::None => name.encoded_len(5) + value.encoded_len(7),
+        };
+        if stream.remaining_mut() < required {
+            return Err(Error::OutOfBuffer);
+        }
+
+        match found {
+            // Duplicate
+            Match::Full(Index::Dynamic(abs)) => write_int(stream, 0x00, 5, self.relative(abs)),
+            // Insert with Name Reference
+            Match::Full(Index::Static(index)) | Match::Name(Index::Static(index)) => {
+                write_int(stream, 0xC0, 6, index);
+                value.write(stream, 0x00, 7)?;
+            }
+            Match::Name(Index::Dynamic(abs)) => {
+                write_int(stream, 0x80, 6, self.relative(abs));
+                value.write(stream, 0x00, 7)?;
+            }
+            // Insert with Literal Name
+            Match::None => {
+                name.write(stream, 0x40, 5)?;
+                value.write(stream, 0x00, 7)?;
+            }
+        }
+
+        // The referenced entry must not be evicted until the decoder has
+        // processed this instruction.
+        if let Match::Full(Index::Dynamic(abs)) | Match::Name(Index::Dynamic(abs)) = found {
+            let min = self.pending_ref.map_or(abs, |(min, _)| min.min(abs));
+            self.pending_ref = Some((min, self.inserted + 1));
+        }
+
+        self.table.evict_to(self.capacity - size);
+        self.table.push(key, &val, len);
+        self.inserted += 1;
+
+        Ok(())
+    }
+
+    /// Encode a field section for the stream `stream_id`.
+    ///
+    /// `fields` is called twice, once to measure the field section and
+    /// once to write it out, and must add the same fields both times.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn encode<B, F>(&mut self, buf: &mut B, stream_id: u64, fields: F) -> Result<()>
+    where
+        B: BufMut,
+        F: Fn(&mut FieldSection<'_>) -> Result<()>,
+    {
+        // > MaxEntries = floor( MaxTableCapacity / 32 )
+        let max_entries = (self.max_capacity / 32) as u64;
+
+        // The field section has to be tracked until it is acknowledged so
+        // that the entries it references are not evicted. If there is no
+        // room to do that, or the decoder's table can't hold any entries,
+        // then only the static table is used.
+        let base = if self.sections.is_full() || max_entries == 0 {
+            0
+        } else {
+            self.known_received
+        };
+
+        let mut len = 0;
+        let refs = {
+            let mut counter = Sink::new(|bytes: &[u8]| len += bytes.len());
+            let mut section = self.section(&mut counter, base);
+            fields(&mut section)?;
+            section.refs
+        };
+
+        // > Required Insert Count  = LargestReference + 1
+        // > EncodedInsertCount = (ReqInsertCount mod (2 * MaxEntries)) + 1
+        let (encoded, delta) = match refs {
+            Some((_, max)) => ((max + 1) % (2 * max_entries) + 1, base - (max + 1)),
+            None => (0, 0),
+        };
+
+        let required = int_len(encoded as usize, 8) + int_len(delta as usize, 7) + len;
+        if buf.remaining_mut() < required {
+            return Err(Error::OutOfBuffer);
+        }
+
+        // The base is never before the required insert count so the sign
+        // bit is always clear.
+        write_int(buf, 0x00, 8, encoded as usize);
+        write_int(buf, 0x00, 7, delta as usize);
+        fields(&mut self.section(buf, base))?;
+
+        if let Some((min, max)) = refs {
+            self.sections.push(Section {
+                stream_id,
+                required_insert_count: max + 1,
+                min_ref: min,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Process instructions received on the decoder stream.
+    ///
+    /// Returns the number of bytes that were processed. A partial
+    /// instruction at the end of `buf` is left unprocessed and should be
+    /// passed in again once the rest of it has been received.
+    pub fn decoder_instructions(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut offset = 0;
+
+        while let Some(&first) = buf.get(offset) {
+            let prefix = if first & 0x80 != 0 { 7 } else { 6 };
+            let (value, len) = match read_int(&buf[offset..], prefix)? {
+                Some(value) => value,
+                None => break,
+            };
+
+            match first {
+                // Section Acknowledgment
+                0x80..=0xFF => self.acknowledge_section(value)?,
+                // Stream Cancellation
+                0x40..=0x7F => self.sections.retain(|section| section.stream_id != value),
+                // Insert Count Increment
+                _ => {
+                    if value == 0 || value > self.inserted - self.known_received {
+                        return Err(Error::InvalidInstruction);
+                    }
+                    self.known_received += value;
+                }
+            }
+
+            if let Some((_, count)) = self.pending_ref {
+                if self.known_received >= count {
+                    self.pending_ref = None;
+                }
+            }
+
+            offset += len;
+        }
+
+        Ok(offset)
+    }
+
+    fn acknowledge_section(&mut self, stream_id: u64) -> Result<()> {
+        // > [...] the Section Acknowledgment instruction [...] is
+        // > acknowledging the earliest unacknowledged field section
+        // > on that stream
+        let pos = self
+            .sections
+            .iter()
+            .position(|section| section.stream_id == stream_id)
+            .ok_or(Error::InvalidInstruction)?;
+
+        let section = self.sections.remove(pos);
+        self.known_received = self.known_received.max(section.required_insert_count);
+
+        Ok(())
+    }
+
+    fn section<'a>(&'a self, out: &'a mut dyn BufMut, base: u64) -> FieldSection<'a> {
+        FieldSection {
+            out,
+            entries: self.table.entries(),
+            first: self.first(),
+            base,
+            huffman: self.huffman,
+            refs: None,
+        }
+    }
+
+    // Absolute index of the oldest entry within the dynamic table.
+    fn first(&self) -> u64 {
+        self.inserted - self.table.len() as u64
+    }
+
+    // Index of an entry relative to the insert count, as used by encoder
+    // instructions.
+    fn relative(&self, abs: u64) -> usize {
+        (self.inserted - 1 - abs) as usize
+    }
+
+    // Whether the `count` oldest entries can be evicted without breaking
+    // any outstanding references to them.
+    fn can_evict(&self, count: usize) -> bool {
+        let referenced = self
+            .sections
+            .iter()
+            .map(|section| section.min_ref)
+            .chain(self.pending_ref.map(|(min, _)| min))
+            .min();
+
+        match referenced {
+            Some(min) => count == 0 || self.first() + count as u64 <= min,
+            None => true,
+        }
+    }
+}
+
+impl<const N: usize> Default for Encoder<N> {
+    fn default() -> Self {
+        Self {
+            table: Table::new(),
+            capacity: 0,
+            max_capacity: 0,
+            inserted: 0,
+            known_received: 0,
+            pending_ref: None,
+            sections: ArrayVec::new(),
+            huffman: true,
+        }
+    }
+}
+
+impl<const N: usize> fmt::Debug for Encoder<N> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Encoder")
+            .field("len", &self.table.len())
+            .field("size", &self.table.size())
+            .field("capacity", &self.capacity)
+            .field("max_capacity", &self.max_capacity)
+            .field("inserted", &self.inserted)
+            .field("known_received", &self.known_received)
+            .field("huffman", &self.huffman)
+            .finish()
+    }
+}
+
+impl FieldSection<'_> {
+    /// Add a field, referencing the static or dynamic table where
+    /// possible.
+    ///
+    /// Field names are lowercased as they are written out. Pseudo-header
+    /// names starting with `:` are accepted.
+    #[inline]
+    pub fn header(&mut self, key: impl AsRef<[u8]>, val: impl HeaderValue) -> Result<&mut Self> {
+        self.field(key.as_ref(), val, false)
+    }
+
+    /// Add a field that intermediaries must never index, such as one
+    /// containing credentials.
+    #[inline]
+    pub fn sensitive_header(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        val: impl HeaderValue,
+    ) -> Result<&mut Self> {
+        self.field(key.as_ref(), val, true)
+    }
+
+    /// Add the `:method` pseudo-header.
+    #[inline]
+    pub fn method(&mut self, method: Method<'_>) -> Result<&mut Self> {
+        self.header(":method", method_bytes(method)?)
+    }
+
+    /// Add the `:scheme` pseudo-header.
+    #[inline]
+    pub fn scheme(&mut self, scheme: &str) -> Result<&mut Self> {
+        self.header(":scheme", scheme)
+    }
+
+    /// Add the `:authority` pseudo-header.
+    #[inline]
+    pub fn authority(&mut self, authority: &str) -> Result<&mut Self> {
+        self.header(":authority", authority)
+    }
+
+    /// Add the `:path` pseudo-header.
+    #[inline]
+    pub fn path(&mut self, uri: Uri<'_>) -> Result<&mut Self> {
+        if !uri.validate() {
+            return Err(Error::InvalidUri);
+        }
+
+        self.header(":path", uri)
+    }
+
+    /// Add the `:status` pseudo-header.
+    #[inline]
+    pub fn status(&mut self, status: Status) -> Result<&mut Self> {
+        self.header(":status", status.code())
+    }
+
+    fn field(&mut self, key: &[u8], val: impl HeaderValue, sensitive: bool) -> Result<&mut Self> {
+        if !validate_name(key) {
+            return Err(Error::InvalidHeaderKey);
+        }
+        if !val.validate() {
+            return Err(Error::InvalidHeaderValue);
+        }
+
+        let len = val.encoded_len();
+        let usable = self.first..self.base;
+        let found = find(key, &val, len, !sensitive, self.entries, self.first, usable);
+
+        let lower = Lowercase(key);
+        let value = Literal::new(&val, len, self.huffman);
+        let never = if sensitive { 0x20 } else { 0x00 };
+
+        match found {
+            // Indexed Field Line
+            Match::Full(Index::Static(index)) => write_int(&mut self.out, 0xC0, 6, index),
+            Match::Full(Index::Dynamic(abs)) => {
+                let index = self.reference(abs);
+                write_int(&mut self.out, 0x80, 6, index);
+            }
+            // Literal Field Line with Name Reference
+            Match::Name(Index::Static(index)) => {
+                write_int(&mut self.out, 0x50 | never, 4, index);
+                value.write(&mut self.out, 0x00, 7)?;
+            }
+            Match::Name(Index::Dynamic(abs)) => {
+                let index = self.reference(abs);
+                write_int(&mut self.out, 0x40 | never, 4, index);
+                value.write(&mut self.out, 0x00, 7)?;
+            }
+            // Literal Field Line with Literal Name
+            Match::None => {
+                let name = Literal::new(&lower, key.len(), self.huffman);
+                name.write(&mut self.out, 0x20 | (never >> 1), 3)?;
+                value.write(&mut self.out, 0x00, 7)?;
+            }
+        }
+
+        Ok(self)
+    }
+
+    // Record a reference to a dynamic table entry, returning its index
+    // relative to the base.
+    fn reference(&mut self, abs: u64) -> usize {
+        self.refs = Some(match self.refs {
+            Some((min, max)) => (min.min(abs), max.max(abs)),
+            None => (abs, abs),
+        });
+
+        (self.base - 1 - abs) as usize
+    }
+}
+
+impl fmt::Debug for FieldSection<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("FieldSection")
+            .field("base", &self.base)
+            .field("huffman", &self.huffman)
+            .finish()
+    }
+}
+
+// Look up the best entry to reference for a field. Only dynamic table
+// entries with an absolute index within `usable` are considered, and
+// exact matches are only considered if `full` is set.
+fn find(
+    key: &[u8],
+    val: &impl HeaderValue,
+    len: usize,
+    full: bool,
+    entries: Entries<'_>,
+    first: u64,
+    usable: Range<u64>,
+) -> Match {
+    let mut name_match = None;
+
+    for (index, &(name, value)) in STATIC_TABLE.iter().enumerate() {
+        if !name.eq_ignore_ascii_case(key) {
+            continue;
+        }
+
+        if full && value_eq(val, len, value) {
+            return Match::Full(Index::Static(index));
+        }
+        if name_match.is_none() {
+            name_match = Some(Index::Static(index));
+        }
+    }
+
+    // Entries are iterated oldest-first so later matches are preferred
+    // as they will be evicted last.
+    let mut dynamic = Match::None;
+    for (abs, (name, value)) in (first..).zip(entries) {
+        if !usable.contains(&abs) || !name.eq_ignore_ascii_case(key) {
+            continue;
+        }
+
+        if full && value_eq(val, len, value) {
+            dynamic = Match::Full(Index::Dynamic(abs));
+        } else if let Match::None | Match::Name(_) = dynamic {
+            dynamic = Match::Name(Index::Dynamic(abs));
+        }
+    }
+
+    match (name_match, dynamic) {
+        (_, Match::Full(index)) => Match::Full(index),
+        (Some(index), _) => Match::Name(index),
+        (None, dynamic) => dynamic,
+    }
+}
+
+// Read an integer with a prefix of `prefix` bits from the start of a
+// non-empty `buf`, as defined in RFC7541 section 5.1. Returns `None` if
+// `buf` does not contain the whole integer.
+fn read_int(buf: &[u8], prefix: u8) -> Result<Option<(u64, usize)>> {
+    let max = (1u64 << prefix) - 1;
+    let mut value = u64::from(buf[0]) & max;
+    if value < max {
+        return Ok(Some((value, 1)));
+    }
+
+    let mut shift = 0;
+    for (i, &byte) in buf[1..].iter().enumerate() {
+        let bits = u64::from(byte & 0x7F);
+        if shift >= 64 || bits > (u64::MAX - value) >> shift {
+            return Err(Error::InvalidInstruction);
+        }
+
+        value += bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 2)));
+        }
+        shift += 7;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // RFC9204 appendix B.1
+    #[test]
+    fn static_only() -> Result<()> {
+        let mut encoder = Encoder::new();
+        encoder.set_huffman(false);
+
+        let mut buf = Vec::new();
+        encoder.encode(&mut buf, 0, |fields| {
+            fields.path(Uri::new(b"/index.html"))?;
+            Ok(())
+        })?;
+        assert_eq!(hex(&buf), "0000510b2f696e6465782e68746d6c");
+
+        buf.clear();
+        encoder.encode(&mut buf, 4, |fields| {
+            fields
+                .method(Method::Get)?
+                .scheme("https")?
+                .path(Uri::new(b"/"))?
+                .sensitive_header("authorization", "secret")?;
+            Ok(())
+        })?;
+        assert_eq!(hex(&buf), "0000d1d7c17f4506736563726574");
+
+        Ok(())
+    }
+
+    // RFC9204 appendix B.2 to B.4
+    #[test]
+    fn dynamic_table() -> Result<()> {
+        let mut encoder = Encoder::new();
+        encoder.set_huffman(false);
+        encoder.set_max_capacity(220)?;
+
+        let mut stream = Vec::new();
+        encoder.set_capacity(&mut stream, 220)?;
+        encoder.insert(&mut stream, ":authority", "www.example.com")?;
+        encoder.insert(&mut stream, ":path", "/sample/path")?;
+        assert_eq!(
+            hex(&stream),
+            "3fbd01c00f7777772e6578616d706c652e636f6dc10c2f73616d706c652f70617468"
+        );
+
+        let fields = |fields: &mut FieldSection<'_>| {
+            fields
+                .authority("www.example.com")?
+                .path(Uri::new(b"/sample/path"))?;
+            Ok(())
+        };
+
+        // Unacknowledged entries are not referenced.
+        let mut buf = Vec::new();
+        encoder.encode(&mut buf, 0, fields)?;
+        assert!(buf.starts_with(&[0x00, 0x00]));
+
+        // Insert Count Increment
+        assert_eq!(encoder.decoder_instructions(&[0x02])?, 1);
+
+        buf.clear();
+        encoder.encode(&mut buf, 4, fields)?;
+        assert_eq!(hex(&buf), "03008180");
+
+        // Referenced entries cannot be evicted until acknowledged.
+        assert_eq!(encoder.set_capacity(&mut stream, 0), Err(Error::TableFull));
+        // Section Acknowledgment
+        assert_eq!(encoder.decoder_instructions(&[0x84])?, 1);
+
+        stream.clear();
+        encoder.insert(&mut stream, "custom-key", "custom-value")?;
+        encoder.insert(&mut stream, ":authority", "www.example.com")?;
+        assert_eq!(
+            hex(&stream),
+            "4a637573746f6d2d6b65790c637573746f6d2d76616c756502"
+        );
+        assert_eq!(encoder.size(), 217);
+
+        encoder.decoder_instructions(&[0x02])?;
+
+        buf.clear();
+        encoder.encode(&mut buf, 8, |fields| {
+            fields
+                .authority("www.example.com")?
+                .path(Uri::new(b"/"))?
+                .header("custom-key", "custom-value")?;
+            Ok(())
+        })?;
+        assert_eq!(hex(&buf), "050080c181");
+
+        // The decoder's maximum capacity can't change once in use.
+        assert_eq!(
+            encoder.set_max_capacity(16),
+            Err(Error::InvalidTableCapacity)
+        );
+        encoder.set_max_capacity(220)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn tiny_max_capacity() -> Result<()> {
+        let mut encoder = Encoder::new();
+        encoder.set_huffman(false);
+        encoder.set_max_capacity(31)?;
+
+        // No entry fits within the table, so only static and literal
+        // representations are used.
+        let mut stream = Vec::new();
+        encoder.set_capacity(&mut stream, 31)?;
+        assert_eq!(
+            encoder.insert(&mut stream, ":authority", "www.example.com"),
+            Err(Error::TableFull)
+        );
+
+        let mut buf = Vec::new();
+        encoder.encode(&mut buf, 0, |fields| {
+            fields.path(Uri::new(b"/index.html"))?;
+            Ok(())
+        })?;
+        assert_eq!(hex(&buf), "0000510b2f696e6465782e68746d6c");
+
+        assert_eq!(
+            encoder.set_max_capacity(4096),
+            Err(Error::InvalidTableCapacity)
+        );
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{HttpBuilder, Method, Result, Uri, Version};
+use crate::util::is_authority_form;
+use crate::{Error, HttpBuilder, Method, Result, Uri, Version};
 use bytes::BufMut;
 
 pub fn get<B: BufMut>(buf: B, path: Uri) -> Result<HttpBuilder<B>> {
@@ -32,3 +33,15 @@ pub fn delete<B: BufMut>(buf: B, path: Uri) -> Result<HttpBuilder<B>> {
 pub fn trace<B: BufMut>(buf: B, path: Uri) -> Result<HttpBuilder<B>> {
     HttpBuilder::request(buf, Method::Trace, Version::Http11, path)
 }
+
+/// Create a `CONNECT` request for a tunnel to `authority`.
+///
+/// Fails with [`Error::InvalidRequestTarget`] if `authority` is not in
+/// authority-form, see [`Uri::authority`].
+pub fn connect<B: BufMut>(buf: B, authority: Uri) -> Result<HttpBuilder<B>> {
+    if !authority.as_bytes().is_some_and(is_authority_form) {
+        return Err(Error::InvalidRequestTarget);
+    }
+
+    HttpBuilder::request(buf, Method::Connect, Version::Http11, authority)
+}
@@ -0,0 +1,108 @@
+use alloc::vec::Vec;
+use bytes::{BufMut, BytesMut};
+
+use core::mem::MaybeUninit;
+
+/// A buffer that can be rolled back to an earlier position.
+///
+/// This is used by [`HttpBuilder::atomic`](crate::HttpBuilder::atomic)
+/// to undo a sequence of writes if any of them fail.
+pub trait Rewind: BufMut {
+    /// Get the current position within the buffer.
+    fn checkpoint(&self) -> usize;
+
+    /// Discard everything written since `checkpoint` was taken.
+    fn rewind(&mut self, checkpoint: usize);
+}
+
+impl Rewind for Vec<u8> {
+    fn checkpoint(&self) -> usize {
+        self.len()
+    }
+
+    fn rewind(&mut self, checkpoint: usize) {
+        self.truncate(checkpoint);
+    }
+}
+
+impl Rewind for BytesMut {
+    fn checkpoint(&self) -> usize {
+        self.len()
+    }
+
+    fn rewind(&mut self, checkpoint: usize) {
+        self.truncate(checkpoint);
+    }
+}
+
+impl<T: Rewind + ?Sized> Rewind for &'_ mut T {
+    fn checkpoint(&self) -> usize {
+        (**self).checkpoint()
+    }
+
+    fn rewind(&mut self, checkpoint: usize) {
+        (**self).rewind(checkpoint)
+    }
+}
+
+/// A cursor for writing into a fixed-size byte slice.
+///
+/// Unlike writing to a `&mut [u8]` directly this keeps track of what has
+/// been written so far so that it can be rewound.
+#[derive(Debug)]
+pub struct SliceBuf<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SliceBuf<'a> {
+    /// Create a cursor writing to the start of `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// The bytes written so far.
+    pub fn filled(&self) -> &[u8] {
+        &self.buf[..self.pos]
+    }
+
+    /// Consume the cursor, returning the bytes written.
+    pub fn into_filled(self) -> &'a mut [u8] {
+        &mut self.buf[..self.pos]
+    }
+}
+
+impl BufMut for SliceBuf<'_> {
+    fn remaining_mut(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(cnt <= self.remaining_mut(), "advance out of bounds");
+
+        self.pos += cnt;
+    }
+
+    fn bytes_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        let rest = &mut self.buf[self.pos..];
+
+        // Safety: MaybeUninit<u8> has the same layout as u8 and the
+        //         slice is already initialized.
+        unsafe { &mut *(rest as *mut [u8] as *mut [MaybeUninit<u8>]) }
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.buf[self.pos..self.pos + src.len()].copy_from_slice(src);
+        self.pos += src.len();
+    }
+}
+
+impl Rewind for SliceBuf<'_> {
+    fn checkpoint(&self) -> usize {
+        self.pos
+    }
+
+    fn rewind(&mut self, checkpoint: usize) {
+        self.pos = self.pos.min(checkpoint);
+    }
+}
@@ -0,0 +1,343 @@
+use bytes::BufMut;
+
+use core::future::{poll_fn, Future};
+use core::pin::{pin, Pin};
+use core::task::{Context, Poll, Waker};
+use std::io;
+
+use crate::traits::ByRef;
+use crate::util::{hex_len, try_write, write_hex};
+use crate::{Error, HeaderValue, HttpBuilder, IoError, SliceBuf};
+
+/// A non-blocking byte sink that an
+/// [`HttpWriter`](crate::asyncio::HttpWriter) can write to.
+pub trait PollWrite {
+    /// Attempt to write bytes from `buf`, returning how many were
+    /// written.
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>;
+
+    /// Attempt to flush any buffered data to its destination.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+/// A non-blocking byte source that bodies can be read from.
+pub trait PollRead {
+    /// Attempt to read bytes into `buf`, returning how many were read.
+    /// Zero indicates the end of the source.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>;
+}
+
+// Size of the largest chunk-size line, including the CRLF.
+pub(crate) const CHUNK_HEADER: usize = 2 * core::mem::size_of::<usize>() + 2;
+
+// Smallest allowed staging buffer. This leaves room for a chunk-size line
+// and trailing CRLF around at least some chunk data.
+const MIN_CAPACITY: usize = 64;
+
+// A fixed-size staging buffer in front of a writer.
+//
+// Everything is first written into the staging buffer and only handed
+// to the writer once the buffer is full or explicitly flushed. Bytes are
+// only dropped from the buffer once the writer has accepted them, so a
+// flush that fails or is cancelled part way through can be retried.
+#[derive(Debug)]
+struct Staging {
+    buf: Box<[u8]>,
+    // The staged bytes that have not been written out yet.
+    start: usize,
+    end: usize,
+}
+
+impl Staging {
+    fn new(capacity: usize) -> Self {
+        assert!(
+            capacity >= MIN_CAPACITY,
+            "staging buffer must be at least {} bytes",
+            MIN_CAPACITY
+        );
+
+        Self {
+            buf: vec![0; capacity].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    // Run `f` against the unused part of the buffer, keeping what it
+    // wrote only if it succeeds.
+    fn stage<F>(&mut self, f: &mut F) -> crate::Result<()>
+    where
+        F: FnMut(&mut SliceBuf<'_>) -> crate::Result<()>,
+    {
+        let mut buf = SliceBuf::new(&mut self.buf[self.end..]);
+        f(&mut buf)?;
+
+        self.end += buf.filled().len();
+
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // The staged bytes that still have to be written out.
+    fn pending(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+
+    // Mark `len` pending bytes as written out.
+    fn consume(&mut self, len: usize) {
+        self.start += len;
+        debug_assert!(self.start <= self.end);
+
+        if self.start == self.end {
+            self.start = 0;
+            self.end = 0;
+        }
+    }
+
+    // Space to read plain body data into. Must only be used when the
+    // buffer is empty.
+    fn body_space(&mut self) -> &mut [u8] {
+        debug_assert!(self.is_empty());
+
+        &mut self.buf[..]
+    }
+
+    // Stage `len` bytes of data that were read into `body_space`.
+    fn stage_body(&mut self, len: usize) {
+        self.end = len;
+    }
+
+    // Space to read chunk data into, leaving room around it for the
+    // chunk framing. Must only be used when the buffer is empty.
+    fn chunk_space(&mut self) -> &mut [u8] {
+        debug_assert!(self.is_empty());
+
+        let end = self.buf.len() - b"\r\n".len();
+        &mut self.buf[CHUNK_HEADER..end]
+    }
+
+    // Frame `len` bytes of data that were read into `chunk_space` as a
+    // chunk and stage the complete chunk.
+    fn stage_chunk(&mut self, len: usize) {
+        let start = CHUNK_HEADER - hex_len(len) - b"\r\n".len();
+        let end = CHUNK_HEADER + len;
+
+        let mut header = SliceBuf::new(&mut self.buf[start..CHUNK_HEADER]);
+        write_hex(&mut header, len);
+        header.put_slice(b"\r\n");
+        self.buf[end..end + 2].copy_from_slice(b"\r\n");
+
+        self.start = start;
+        self.end = end + 2;
+    }
+}
+
+// The write loop shared by the asynchronous and blocking `HttpWriter`s.
+// Operations are staged and the staging buffer is flushed whenever an
+// operation does not fit.
+//
+// The blocking writer drives the same futures with `block_on`, through
+// an adapter that is never pending.
+#[derive(Debug)]
+pub(crate) struct Writer<W> {
+    io: W,
+    staging: Staging,
+}
+
+impl<W: PollWrite + Unpin> Writer<W> {
+    pub(crate) fn new(io: W, capacity: usize) -> Self {
+        Self {
+            io,
+            staging: Staging::new(capacity),
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &W {
+        &self.io
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.io
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.io
+    }
+
+    // Run `f` against the staging buffer, flushing it and retrying once
+    // if `f` runs out of space.
+    pub(crate) async fn stage<F>(&mut self, mut f: F) -> Result<(), IoError>
+    where
+        F: FnMut(&mut SliceBuf<'_>) -> crate::Result<()>,
+    {
+        match self.staging.stage(&mut f) {
+            Err(Error::OutOfBuffer) if !self.staging.is_empty() => {
+                self.flush_staging().await?;
+                Ok(self.staging.stage(&mut f)?)
+            }
+            res => Ok(res?),
+        }
+    }
+
+    pub(crate) async fn header<V: HeaderValue>(
+        &mut self,
+        key: &[u8],
+        val: &V,
+    ) -> Result<(), IoError> {
+        self.headers(|builder| builder.header(key, ByRef(val)).map(drop))
+            .await
+    }
+
+    pub(crate) async fn headers<F>(&mut self, mut f: F) -> Result<(), IoError>
+    where
+        F: FnMut(&mut HttpBuilder<&mut SliceBuf<'_>>) -> crate::Result<()>,
+    {
+        self.stage(|buf| {
+            // Safety: Headers are only written within the header section.
+            let mut builder = unsafe { HttpBuilder::from_buf(buf) };
+            f(&mut builder)
+        })
+        .await
+    }
+
+    // Stage `data`, or write it out directly if it doesn't fit.
+    pub(crate) async fn body(&mut self, data: &[u8]) -> Result<(), IoError> {
+        match self.stage(|buf| try_write(buf, data)).await {
+            Err(IoError::Encode(Error::OutOfBuffer)) => write_all(&mut self.io, data).await,
+            res => res,
+        }
+    }
+
+    pub(crate) async fn body_reader<R: PollRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<u64, IoError> {
+        let mut total = 0;
+
+        loop {
+            self.flush_staging().await?;
+
+            let len = read(reader, self.staging.body_space()).await?;
+            if len == 0 {
+                return Ok(total);
+            }
+
+            self.staging.stage_body(len);
+            total += len as u64;
+        }
+    }
+
+    // Copy the body from `reader` as chunks, not including the last
+    // chunk.
+    pub(crate) async fn chunked_reader<R: PollRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<u64, IoError> {
+        let mut total = 0;
+
+        loop {
+            self.flush_staging().await?;
+
+            let len = read(reader, self.staging.chunk_space()).await?;
+            if len == 0 {
+                return Ok(total);
+            }
+
+            self.staging.stage_chunk(len);
+            total += len as u64;
+        }
+    }
+
+    // Write out `data` as a single chunk. Empty chunks are skipped as
+    // they would end the body.
+    pub(crate) async fn chunk(&mut self, data: &[u8]) -> Result<(), IoError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.stage(|buf| write_chunk_size(buf, data.len())).await?;
+        self.body(data).await?;
+        self.stage(|buf| try_write(buf, b"\r\n")).await
+    }
+
+    pub(crate) async fn last_chunk(&mut self) -> Result<(), IoError> {
+        self.stage(|buf| try_write(buf, b"0\r\n\r\n")).await
+    }
+
+    pub(crate) async fn flush(&mut self) -> Result<(), IoError> {
+        self.flush_staging().await?;
+
+        let io = &mut self.io;
+        poll_fn(|cx| Pin::new(&mut *io).poll_flush(cx)).await?;
+
+        Ok(())
+    }
+
+    // Write out the staging buffer, consuming each write as soon as it
+    // completes so that nothing is written twice if this is retried.
+    async fn flush_staging(&mut self) -> Result<(), IoError> {
+        while !self.staging.is_empty() {
+            let len = write(&mut self.io, self.staging.pending()).await?;
+            self.staging.consume(len);
+        }
+
+        Ok(())
+    }
+}
+
+async fn write<W: PollWrite + Unpin>(io: &mut W, buf: &[u8]) -> Result<usize, IoError> {
+    match poll_fn(|cx| Pin::new(&mut *io).poll_write(cx, buf)).await? {
+        0 => Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+        len => Ok(len),
+    }
+}
+
+async fn write_all<W: PollWrite + Unpin>(io: &mut W, mut buf: &[u8]) -> Result<(), IoError> {
+    while !buf.is_empty() {
+        let len = write(io, buf).await?;
+        buf = &buf[len..];
+    }
+
+    Ok(())
+}
+
+async fn read<R: PollRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<usize, IoError> {
+    let len = poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, buf)).await?;
+
+    Ok(len)
+}
+
+// Run a future that is never pending, such as one writing to blocking
+// I/O, to completion.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    match future.as_mut().poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("future was pending"),
+    }
+}
+
+// Write out a chunk-size line for a chunk of `len` bytes.
+fn write_chunk_size(buf: &mut SliceBuf<'_>, len: usize) -> crate::Result<()> {
+    if buf.remaining_mut() < hex_len(len) + 2 {
+        return Err(Error::OutOfBuffer);
+    }
+
+    write_hex(buf, len);
+    buf.put_slice(b"\r\n");
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! A builder that validates message-level rules from RFC 7230 and
+//! RFC 7231.
+//!
+//! [`HttpBuilder`] only checks that the message is syntactically valid.
+//! [`StrictBuilder`] additionally keeps track of the headers that have
+//! been written and rejects messages that are well-formed but
+//! semantically invalid:
+//!
+//! - HTTP/1.1 requests must have exactly one `Host` header.
+//! - `CONNECT` requests must use the authority-form (`host:port`).
+//! - Only `OPTIONS` requests may use the asterisk-form (`*`).
+//! - `TRACE` requests must not have a body.
+//! - 1xx, 204, and 304 responses must not have a body or any framing
+//!   headers.
+//! - Responses to `HEAD` requests must not have a body.
+//!
+//! ```
+//! # use httpencode::*;
+//! # use httpencode::strict::StrictBuilder;
+//! # fn main() -> Result<(), Error> {
+//! let req = StrictBuilder::request(Vec::new(), Method::Get, Version::Http11, Uri::new(b"/"))?;
+//! assert_eq!(req.finish().err(), Some(Error::MissingHost));
+//!
+//! let mut req = StrictBuilder::request(Vec::new(), Method::Get, Version::Http11, Uri::new(b"/"))?;
+//! req.header("Host", "example.com")?;
+//! assert_eq!(req.header("Host", "example.org").err(), Some(Error::DuplicateHost));
+//! req.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::{Buf, BufMut};
+
+use crate::framed::{is_framing_header, status_has_no_body};
+use crate::util::is_authority_form;
+use crate::{ChunkedWriter, Error, HeaderValue, HttpBuilder, Method, Result, Status, Uri, Version};
+
+/// Builder that validates the semantics of the message as it is written.
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Debug)]
+pub struct StrictBuilder<B: BufMut> {
+    builder: HttpBuilder<B>,
+    is_request: bool,
+    requires_host: bool,
+    has_host: bool,
+    framing_allowed: bool,
+    body_allowed: bool,
+}
+
+impl<B: BufMut> StrictBuilder<B> {
+    /// Create a new request with the provided request line.
+    ///
+    /// Fails with [`Error::InvalidRequestTarget`] if `method` is `CONNECT`
+    /// and `uri` is not in authority-form, or if `uri` is in asterisk-form
+    /// and `method` is not `OPTIONS`.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn request(buf: B, method: Method, version: Version, uri: Uri) -> Result<Self> {
+        if method == Method::Connect && !uri.as_bytes().is_some_and(is_authority_form) {
+            return Err(Error::InvalidRequestTarget);
+        }
+        if method != Method::Options && uri.as_bytes() == Some(b"*") {
+            return Err(Error::InvalidRequestTarget);
+        }
+
+        let trace = method == Method::Trace;
+
+        Ok(Self {
+            builder: HttpBuilder::request(buf, method, version, uri)?,
+            is_request: true,
+            requires_host: version == Version::Http11,
+            has_host: false,
+            framing_allowed: !trace,
+            body_allowed: !trace,
+        })
+    }
+
+    /// Create a new response to a request made with `method`.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn response(buf: B, method: Method, version: Version, status: Status) -> Result<Self> {
+        let no_body = status_has_no_body(status);
+
+        Ok(Self {
+            builder: HttpBuilder::response(buf, version, status)?,
+            is_request: false,
+            requires_host: false,
+            has_host: false,
+            framing_allowed: !no_body,
+            body_allowed: !no_body && method != Method::Head,
+        })
+    }
+
+    /// Add a new header to the message.
+    ///
+    /// This fails with [`Error::DuplicateHost`] if a request already has a
+    /// `Host` header and with [`Error::BodyNotAllowed`] if a framing header
+    /// is written for a message that can't have a body.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn header(&mut self, key: impl AsRef<[u8]>, val: impl HeaderValue) -> Result<&mut Self> {
+        let key = key.as_ref();
+        let is_host = self.is_request && key.eq_ignore_ascii_case(b"host");
+
+        if is_host && self.has_host {
+            return Err(Error::DuplicateHost);
+        }
+        if !self.framing_allowed && is_framing_header(key) {
+            return Err(Error::BodyNotAllowed);
+        }
+
+        self.builder.header(key, val)?;
+        self.has_host |= is_host;
+
+        Ok(self)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.requires_host && !self.has_host {
+            return Err(Error::MissingHost);
+        }
+
+        Ok(())
+    }
+
+    /// Complete the HTTP header and return the underlying buffer.
+    pub fn finish(self) -> Result<B> {
+        self.validate()?;
+        self.builder.finish()
+    }
+
+    /// Complete the HTTP header and write out the body.
+    ///
+    /// Fails with [`Error::BodyNotAllowed`] if `body` is not empty and the
+    /// message is not allowed to have a body.
+    pub fn body<I: Buf>(self, body: &mut I) -> Result<B> {
+        self.validate()?;
+
+        if !self.body_allowed && body.has_remaining() {
+            return Err(Error::BodyNotAllowed);
+        }
+
+        self.builder.body(body)
+    }
+
+    /// Complete the HTTP header and start writing a body using the
+    /// chunked transfer coding.
+    ///
+    /// As with [`HttpBuilder::chunked`], the `Transfer-Encoding` header
+    /// must be written by the caller.
+    pub fn chunked(self) -> Result<ChunkedWriter<B>> {
+        self.validate()?;
+
+        if !self.body_allowed {
+            return Err(Error::BodyNotAllowed);
+        }
+
+        self.builder.chunked()
+    }
+
+    /// Get the underlying buffer for this message.
+    pub fn into_buf(self) -> B {
+        self.builder.into_buf()
+    }
+
+    /// Get the number of remaining bytes within the underlying buffer.
+    pub fn remaining(&self) -> usize {
+        self.builder.remaining()
+    }
+}
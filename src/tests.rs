@@ -3,7 +3,7 @@ use crate::*;
 
 use std::borrow::Cow;
 
-fn escaped(bytes: &[u8]) -> Cow<str> {
+fn escaped(bytes: &[u8]) -> Cow<'_, str> {
     if bytes.iter().copied().all(|c| c.is_ascii()) {
         Cow::Borrowed(std::str::from_utf8(bytes).unwrap())
     } else {
@@ -107,6 +107,10 @@ fn test_write_status() {
     out.clear();
     write_status(&mut out, Status::IM_A_TEAPOT).unwrap();
     assert_eq!(escaped(&out), "418");
+
+    out.clear();
+    write_status(&mut out, Status::new(7)).unwrap();
+    assert_eq!(escaped(&out), "007");
 }
 
 #[test]
@@ -262,3 +266,1445 @@ fn test_body_doesnt_bail() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn chunked_body() -> Result<()> {
+    #[rustfmt::skip]
+    let expected = "\
+        HTTP/1.1 200 OK\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        1A;name=token;quoted=\"a \\\"b\\\"\";flag\r\n\
+        abcdefghijklmnopqrstuvwxyz\r\n\
+        0\r\n\
+        \r\n\
+    ";
+
+    let mut buf = Vec::new();
+    let mut rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK)?;
+    rsp.header("Transfer-Encoding", "chunked")?;
+
+    let mut body = rsp.chunked()?;
+    body.write_chunk(&mut &b"Hello"[..])?;
+    body.write_chunk(&mut &b""[..])?;
+    body.write_chunk_with_ext(
+        &[
+            ("name", Some("token")),
+            ("quoted", Some("a \"b\"")),
+            ("flag", None),
+        ],
+        &mut &b"abcdefghijklmnopqrstuvwxyz"[..],
+    )?;
+    body.finish()?;
+
+    assert_eq!(escaped(&buf), expected);
+
+    Ok(())
+}
+
+#[test]
+fn chunked_body_is_atomic() -> Result<()> {
+    let mut bytes = [0u8; 16];
+    let mut buf = &mut bytes[..];
+
+    let mut body = ChunkedWriter::new(&mut buf, Default::default());
+    body.write_chunk(&mut &b"0123"[..])?;
+
+    let mut data: &[u8] = b"0123456789";
+    assert_eq!(body.write_chunk(&mut data).unwrap_err(), Error::OutOfBuffer);
+    assert_eq!(data.len(), 10);
+    assert_eq!(
+        body.write_chunk_with_ext(&[("bad name", None)], &mut &b"0"[..])
+            .unwrap_err(),
+        Error::InvalidChunkExtension
+    );
+    assert_eq!(body.remaining(), 7);
+
+    body.finish()?;
+    assert_eq!(escaped(&bytes[..14]), "4\r\n0123\r\n0\r\n\r\n");
+
+    Ok(())
+}
+
+#[test]
+fn chunked_trailers() -> Result<()> {
+    #[rustfmt::skip]
+    let expected = "\
+        HTTP/1.1 200 OK\r\n\
+        Transfer-Encoding: chunked\r\n\
+        Trailer: Digest, Grpc-Status\r\n\
+        \r\n\
+        5\r\n\
+        Hello\r\n\
+        0\r\n\
+        Digest: sha-256=abc\r\n\
+        grpc-status: 0\r\n\
+        \r\n\
+    ";
+
+    let mut buf = Vec::new();
+    let mut rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK)?;
+    rsp.header("Transfer-Encoding", "chunked")?;
+    rsp.header("Trailer", "Digest, Grpc-Status")?;
+
+    let mut body = rsp.chunked()?;
+    body.write_chunk(&mut &b"Hello"[..])?;
+
+    let mut trailers = body.trailers()?;
+    trailers.trailer("Digest", "sha-256=abc")?;
+    trailers.trailer("grpc-status", 0u8)?;
+
+    assert_eq!(
+        trailers.trailer("Grpc-Message", "").unwrap_err(),
+        Error::UndeclaredTrailer
+    );
+    assert_eq!(
+        trailers.trailer("Content-Length", 5u8).unwrap_err(),
+        Error::ForbiddenTrailer
+    );
+    assert_eq!(
+        trailers.trailer("Digest", "\r\n").unwrap_err(),
+        Error::InvalidHeaderValue
+    );
+
+    trailers.finish()?;
+
+    assert_eq!(escaped(&buf), expected);
+
+    Ok(())
+}
+
+#[test]
+fn chunked_trailers_declared() -> Result<()> {
+    let mut buf = Vec::new();
+    let mut rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK)?;
+    rsp.header("Trailer", "Digest")?;
+    rsp.header("trailer", "grpc-status ,\tGrpc-Message")?;
+
+    let mut trailers = rsp.chunked()?.trailers()?;
+    trailers.trailer("grpc-message", "")?;
+    trailers.trailer("GRPC-STATUS", 0u8)?;
+    assert_eq!(
+        trailers.trailer("A", "").unwrap_err(),
+        Error::UndeclaredTrailer
+    );
+    assert_eq!(
+        trailers.trailer("Grpc", "").unwrap_err(),
+        Error::UndeclaredTrailer
+    );
+
+    // Without a `Trailer` header any allowed field can be sent.
+    let mut buf = Vec::new();
+    let rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK)?;
+    rsp.chunked()?
+        .trailers()?
+        .trailer("Server-Timing", "db;dur=53")?;
+
+    // Declaring more names than can be tracked never fails the header and
+    // stops trailer fields from being checked.
+    let names = ["X-Trailer-Field"; 10].join(", ");
+    let mut buf = Vec::new();
+    let mut rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK)?;
+    rsp.header("Content-Length", 0u8)?;
+    rsp.header("Trailer", names.as_str())?;
+    rsp.chunked()?.trailers()?.trailer("Server-Timing", "")?;
+
+    Ok(())
+}
+
+#[test]
+fn decode_round_trip_request() -> Result<()> {
+    let mut buf = Vec::new();
+    let mut req = HttpBuilder::request(
+        &mut buf,
+        Method::Custom("PURGE"),
+        Version::Http11,
+        Uri::new(b"/search?q=a b&lang=\"en\""),
+    )?;
+    req.header("Host", "example.com")?;
+    req.header("X-Empty", "")?;
+    req.header("Content-Length", 0u8)?;
+    req.finish()?;
+
+    let (line, len) = decode::request_line(&buf)?.unwrap();
+    assert_eq!(line.method, Method::Custom("PURGE"));
+    assert_eq!(line.version, Version::Http11);
+    assert_eq!(
+        escaped(line.uri.as_bytes().unwrap()),
+        "/search%3Fq=a%20b&lang=%22en%22"
+    );
+
+    let mut headers = [decode::Header::EMPTY; 4];
+    let (headers, hlen) = decode::headers(&buf[len..], &mut headers)?.unwrap();
+    assert_eq!(len + hlen, buf.len());
+    assert_eq!(headers.len(), 3);
+    assert_eq!(headers[0].name, b"Host");
+    assert_eq!(headers[0].value, b"example.com");
+    assert_eq!(headers[1].value, b"");
+    assert_eq!(headers[2].value, b"0");
+
+    let mut out = Vec::new();
+    let mut req = HttpBuilder::request(&mut out, line.method, line.version, line.uri)?;
+    for header in headers {
+        req.header(header.name, header.value)?;
+    }
+    req.finish()?;
+    assert_eq!(escaped(&out), escaped(&buf));
+
+    Ok(())
+}
+
+#[test]
+fn decode_round_trip_response() -> Result<()> {
+    let mut buf = Vec::new();
+    let rsp = HttpBuilder::response(&mut buf, Version::Http10, Status::IM_A_TEAPOT)?;
+    rsp.finish()?;
+
+    let (line, len) = decode::status_line(&buf)?.unwrap();
+    assert_eq!(line.version, Version::Http10);
+    assert_eq!(line.status, Status::IM_A_TEAPOT);
+    assert_eq!(line.reason, "I'm a Teapot");
+    assert_eq!(decode::headers(&buf[len..], &mut [])?.unwrap().1, 2);
+
+    Ok(())
+}
+
+#[test]
+fn decode_rejects_invalid() {
+    use crate::decode::{headers, request_line, status_line, Header};
+
+    assert!(request_line(b"GET / HTTP/1.1").unwrap().is_none());
+    assert!(request_line(b"GET / HTTP/1.1\r").unwrap().is_none());
+    assert_eq!(
+        request_line(b"GET / HTTP/1.1\n").unwrap_err(),
+        Error::InvalidLineEnding
+    );
+    assert_eq!(
+        request_line(b"GET /\r\n").unwrap_err(),
+        Error::InvalidStartLine
+    );
+    assert_eq!(
+        request_line(b"GET /a b HTTP/1.1\r\n").unwrap_err(),
+        Error::InvalidVersion
+    );
+    assert_eq!(
+        request_line(b"GET /%zz HTTP/1.1\r\n").unwrap_err(),
+        Error::InvalidUri
+    );
+    assert_eq!(
+        request_line(b" / HTTP/1.1\r\n").unwrap_err(),
+        Error::InvalidMethod
+    );
+    assert_eq!(
+        status_line(b"HTTP/1.1 20 OK\r\n").unwrap_err(),
+        Error::InvalidStatus
+    );
+    assert_eq!(
+        status_line(b"HTTP/1.1 200 \x7f\r\n").unwrap_err(),
+        Error::InvalidReason
+    );
+
+    let mut storage = [Header::EMPTY; 1];
+    assert_eq!(
+        headers(b"\"Key\": value\r\n\r\n", &mut storage).unwrap_err(),
+        Error::InvalidHeaderKey
+    );
+    assert_eq!(
+        headers(b"Key: \xff\r\n\r\n", &mut storage).unwrap_err(),
+        Error::InvalidHeaderValue
+    );
+    assert_eq!(
+        headers(b"A: 1\r\nB: 2\r\n\r\n", &mut storage).unwrap_err(),
+        Error::TooManyHeaders
+    );
+    assert!(headers(b"A: 1\r\n", &mut storage).unwrap().is_none());
+
+    // The encoder refuses to write anything that the parser rejects.
+    let mut buf = Vec::new();
+    for (version, reason, error) in [
+        (Version::Custom(""), "OK", Error::InvalidVersion),
+        (Version::Custom("HTTP/\x01"), "OK", Error::InvalidVersion),
+        (Version::Http11, "\x7f", Error::InvalidReason),
+        (Version::Http11, "OK\r\nX-Injected: 1", Error::InvalidReason),
+    ] {
+        let res = HttpBuilder::response_with_reason(&mut buf, version, Status::OK, reason);
+        assert_eq!(res.err(), Some(error));
+    }
+    for method in ["", "GET\x00"] {
+        let uri = Uri::new(b"/");
+        let res = HttpBuilder::request(&mut buf, Method::Custom(method), Version::Http11, uri);
+        assert_eq!(res.err(), Some(Error::InvalidMethod));
+    }
+    let mut rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK).unwrap();
+    assert_eq!(rsp.header("\"Key\"", "value").err(), Some(Error::InvalidHeaderKey));
+    assert_eq!(escaped(&buf), "HTTP/1.1 200 OK\r\n");
+
+    // A reason phrase may contain obs-text, which is read back as is.
+    let mut buf = Vec::new();
+    HttpBuilder::response_with_reason(&mut buf, Version::Http11, Status::OK, "Tr\u{e8}s bien")
+        .unwrap();
+    let (line, _) = status_line(&buf).unwrap().unwrap();
+    assert_eq!(line.reason, "Tr\u{e8}s bien");
+}
+
+#[test]
+fn request_line_is_atomic() {
+    let mut data = [0u8; 16];
+    let mut buf = SliceBuf::new(&mut data);
+
+    let uri = Uri::new(b"/a/rather/long/path");
+    let res = HttpBuilder::request(&mut buf, Method::Get, Version::Http11, uri);
+    assert_eq!(res.err(), Some(Error::OutOfBuffer));
+    assert_eq!(buf.filled(), b"");
+
+    let res = HttpBuilder::response_with_reason(&mut buf, Version::Http11, Status::OK, "Okay Then");
+    assert_eq!(res.err(), Some(Error::OutOfBuffer));
+    assert_eq!(buf.filled(), b"");
+
+    // Validation happens up front when there's plenty of space.
+    let mut buf = Vec::new();
+    let uri = Uri::new(b"/");
+    let res = HttpBuilder::request(&mut buf, Method::Get, Version::Custom("A B"), uri);
+    assert_eq!(res.err(), Some(Error::InvalidVersion));
+    assert_eq!(buf, b"");
+
+    // The request line is measured exactly once the buffer is tight.
+    let mut data = [0u8; 24];
+    let mut buf = SliceBuf::new(&mut data);
+    HttpBuilder::request(&mut buf, Method::Get, Version::Http11, Uri::new(b"/a b")).unwrap();
+    assert_eq!(buf.filled(), b"GET /a%20b HTTP/1.1\r\n");
+}
+
+#[test]
+fn atomic_headers() -> Result<()> {
+    let mut buf = Vec::new();
+    let mut rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK)?;
+
+    rsp.atomic(|rsp| {
+        rsp.header("Content-Type", "text/plain")?;
+        rsp.header("Content-Length", 5u32)?;
+        Ok(())
+    })?;
+
+    let res = rsp.atomic(|rsp| {
+        rsp.header("X-First", "ok")?;
+        rsp.header("X-Second", "\x7f")?;
+        Ok(())
+    });
+    assert_eq!(res, Err(Error::InvalidHeaderValue));
+
+    rsp.finish()?;
+
+    #[rustfmt::skip]
+    let expected = "\
+        HTTP/1.1 200 OK\r\n\
+        Content-Type: text/plain\r\n\
+        Content-Length: 5\r\n\
+        \r\n\
+    ";
+    assert_eq!(escaped(&buf), expected);
+
+    Ok(())
+}
+
+#[test]
+fn atomic_restores_declared_trailers() -> Result<()> {
+    let mut buf = Vec::new();
+    let mut rsp = HttpBuilder::response(&mut buf, Version::Http11, Status::OK)?;
+    rsp.header("Trailer", "Digest")?;
+
+    let res = rsp.atomic(|rsp| {
+        rsp.header("Trailer", "Grpc-Status")?;
+        rsp.header("X-Invalid", "\x7f")?;
+        Ok(())
+    });
+    assert_eq!(res, Err(Error::InvalidHeaderValue));
+
+    let mut trailers = rsp.chunked()?.trailers()?;
+    trailers.trailer("Digest", "sha-256=abc")?;
+    assert_eq!(
+        trailers.trailer("Grpc-Status", 0u8).unwrap_err(),
+        Error::UndeclaredTrailer
+    );
+
+    Ok(())
+}
+
+#[test]
+fn measure_matches_output() -> Result<()> {
+    fn write<B: bytes::BufMut>(buf: B) -> Result<B> {
+        let uri = Uri::new(b"/search?q=caf\xc3\xa9 au lait");
+        let mut req = HttpBuilder::request(buf, Method::Post, Version::Http11, uri)?;
+
+        req.header("Host", "example.com")?;
+        req.header("Content-Length", 1234u64)?;
+        req.header("X-Offset", -56i32)?;
+        req.body(&mut &b"body"[..])
+    }
+
+    let count = write(Counter::new())?.count();
+    let buf = write(Vec::new())?;
+    assert_eq!(count, buf.len());
+
+    Ok(())
+}
+
+#[test]
+fn vectored_borrows_values() -> Result<()> {
+    let cookie: &[u8] = b"session=0123456789abcdef; theme=dark";
+    let body: &[u8] = b"hello world";
+
+    let mut req = VectoredBuilder::request(Method::Post, Version::Http11, Uri::new(b"/"))?;
+    req.header("Host", "example.com")?;
+    req.header_ref("Cookie", cookie)?;
+    req.header("Content-Length", body.len())?;
+    let msg = req.body(body);
+
+    let slices: Vec<_> = msg.slices().collect();
+    assert_eq!(slices.len(), 4);
+    assert!(core::ptr::eq(slices[1], cookie));
+    assert!(core::ptr::eq(slices[3], body));
+
+    let mut buf = Vec::new();
+    let mut expected =
+        HttpBuilder::request(&mut buf, Method::Post, Version::Http11, Uri::new(b"/"))?;
+    expected.header("Host", "example.com")?;
+    expected.header("Cookie", cookie)?;
+    expected.header("Content-Length", body.len())?;
+    expected.body(&mut &body[..])?;
+
+    // Write the message out a few bytes at a time.
+    struct Trickle(Vec<u8>);
+
+    impl std::io::Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(7);
+            self.0.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut out = Trickle(Vec::new());
+    msg.write_to(&mut out).unwrap();
+    assert_eq!(msg.len(), buf.len());
+    assert_eq!(escaped(&out.0), escaped(&buf));
+
+    Ok(())
+}
+
+#[test]
+fn framed_chunked() -> Result<()> {
+    use crate::framed::FramedBuilder;
+
+    let mut buf = Vec::new();
+    let req = FramedBuilder::request(&mut buf, Method::Post, Version::Http11, Uri::new(b"/"))?;
+    let mut chunked = req.chunked()?.body()?;
+    chunked.write_chunk(&mut &b"abc"[..])?;
+    chunked.finish()?;
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn framed_rejects_conflicts() -> Result<()> {
+    use crate::framed::FramedBuilder;
+
+    let mut buf = Vec::new();
+    let mut res = FramedBuilder::response(&mut buf, Version::Http11, Status::OK)?.chunked()?;
+    assert_eq!(
+        res.header("content-length", 5u32).err(),
+        Some(Error::ConflictingFraming)
+    );
+    assert_eq!(
+        res.header("Transfer-Encoding", "chunked").err(),
+        Some(Error::ConflictingFraming)
+    );
+
+    let res = FramedBuilder::response(Vec::new(), Version::Http11, Status::OK)?;
+    let res = res.content_length(3)?;
+    assert_eq!(
+        res.body(&mut &b"ab"[..]).err(),
+        Some(Error::BodyLengthMismatch)
+    );
+
+    assert_eq!(
+        FramedBuilder::response(Vec::new(), Version::Http11, Status::NO_CONTENT).err(),
+        Some(Error::BodyNotAllowed)
+    );
+    let res = FramedBuilder::bodyless_response(Vec::new(), Version::Http11, Status::NOT_MODIFIED)?;
+    assert_eq!(
+        escaped(&res.finish()?),
+        escaped(b"HTTP/1.1 304 Not Modified\r\n\r\n")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn strict_requests() -> Result<()> {
+    use crate::strict::StrictBuilder;
+
+    let connect = |uri: &'static [u8]| {
+        StrictBuilder::request(Vec::new(), Method::Connect, Version::Http11, Uri::new(uri))
+    };
+    assert!(connect(b"example.com:443").is_ok());
+    assert!(connect(b"[::1]:8080").is_ok());
+    assert_eq!(connect(b"/").err(), Some(Error::InvalidRequestTarget));
+    assert_eq!(
+        connect(b"example.com").err(),
+        Some(Error::InvalidRequestTarget)
+    );
+
+    let mut req =
+        StrictBuilder::request(Vec::new(), Method::Trace, Version::Http11, Uri::new(b"/"))?;
+    req.header("Host", "example.com")?;
+    assert_eq!(
+        req.header("Content-Length", 0u8).err(),
+        Some(Error::BodyNotAllowed)
+    );
+    assert_eq!(req.body(&mut &b"x"[..]).err(), Some(Error::BodyNotAllowed));
+
+    let req = StrictBuilder::request(Vec::new(), Method::Get, Version::Http10, Uri::new(b"/"))?;
+    assert!(req.finish().is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn strict_responses() -> Result<()> {
+    use crate::strict::StrictBuilder;
+
+    let mut res =
+        StrictBuilder::response(Vec::new(), Method::Get, Version::Http11, Status::NO_CONTENT)?;
+    assert_eq!(
+        res.header("Content-Length", 0u8).err(),
+        Some(Error::BodyNotAllowed)
+    );
+    assert_eq!(res.chunked().err(), Some(Error::BodyNotAllowed));
+
+    let mut res = StrictBuilder::response(Vec::new(), Method::Head, Version::Http11, Status::OK)?;
+    res.header("Content-Length", 5u8)?;
+    assert_eq!(
+        res.body(&mut &b"hello"[..]).err(),
+        Some(Error::BodyNotAllowed)
+    );
+
+    let res = StrictBuilder::response(Vec::new(), Method::Head, Version::Http11, Status::OK)?;
+    assert_eq!(escaped(&res.finish()?), escaped(b"HTTP/1.1 200 OK\r\n\r\n"));
+
+    Ok(())
+}
+
+#[test]
+fn uri_builder_escapes_components() -> Result<()> {
+    let mut out = vec![];
+    write_uri(&mut out, UriBuilder::new().build())?;
+    assert_eq!(escaped(&out), "/");
+
+    out.clear();
+    let uri = UriBuilder::new()
+        .segments(&["a?b", "c:d@e", "100%"])
+        .query(&[("k+1", "a/b?c"), ("empty", "")])
+        .fragment("ignored");
+    write_uri(&mut out, uri.into())?;
+    assert_eq!(escaped(&out), "/a%3Fb/c:d@e/100%25?k%2B1=a/b?c&empty=");
+
+    // The builder is kept as-is, apart from the fragment.
+    let parts = uri.build().parts().unwrap();
+    assert_eq!(parts.encoded_len(), out.len());
+    assert_eq!(uri.build().as_bytes(), None);
+    assert_eq!(Uri::new(b"/a b").as_bytes(), Some(&b"/a b"[..]));
+
+    out.clear();
+    let uri = UriBuilder::new().segments(&["files", "...", "a.b"]);
+    write_uri(&mut out, uri.build())?;
+    assert_eq!(escaped(&out), "/files/.../a.b");
+    assert!(Uri::new(b"/a b").parts().is_none());
+
+    // Dot-segments are rejected before anything is written.
+    for segments in [&["files", ".."][..], &["."]] {
+        let uri = UriBuilder::new().segments(segments);
+        assert_eq!(write_uri(&mut out, uri.build()), Err(Error::InvalidUri));
+        assert!(!uri.validate());
+        assert!(!uri.build().validate());
+
+        let mut buf = Vec::new();
+        let res = HttpBuilder::request(&mut buf, Method::Get, Version::Http11, uri.build());
+        assert_eq!(res.err(), Some(Error::InvalidUri));
+        assert_eq!(buf, b"");
+    }
+    assert_eq!(escaped(&out), "/files/.../a.b");
+
+    let mut out = [0u8; 8];
+    assert_eq!(
+        write_uri(&mut &mut out[..], uri.build()),
+        Err(Error::OutOfBuffer)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn request_target_forms() -> Result<()> {
+    assert!(Uri::absolute(b"http://example.com").is_ok());
+    assert!(Uri::absolute(b"https://[::1]:8443/a%20b?q=1").is_ok());
+    assert_eq!(Uri::absolute(b"/path").err(), Some(Error::InvalidUri));
+    assert_eq!(
+        Uri::absolute(b"http://user@host/").err(),
+        Some(Error::InvalidUri)
+    );
+    assert_eq!(
+        Uri::absolute(b"http://host/#frag").err(),
+        Some(Error::InvalidUri)
+    );
+    assert_eq!(
+        Uri::absolute(b"1http://host/").err(),
+        Some(Error::InvalidUri)
+    );
+
+    assert!(Uri::authority(b"[::1]:443").is_ok());
+    assert_eq!(
+        Uri::authority(b"example.com").err(),
+        Some(Error::InvalidUri)
+    );
+    assert_eq!(
+        Uri::authority(b"example.com:https").err(),
+        Some(Error::InvalidUri)
+    );
+
+    let mut buf = Vec::new();
+    request::connect(&mut buf, Uri::authority(b"example.com:443")?)?.finish()?;
+    assert_eq!(
+        escaped(&buf),
+        escaped(b"CONNECT example.com:443 HTTP/1.1\r\n\r\n")
+    );
+    assert_eq!(
+        request::connect(Vec::new(), Uri::new(b"/")).err(),
+        Some(Error::InvalidRequestTarget)
+    );
+
+    let mut buf = Vec::new();
+    request::options(&mut buf, Uri::asterisk())?.finish()?;
+    assert_eq!(escaped(&buf), escaped(b"OPTIONS * HTTP/1.1\r\n\r\n"));
+
+    let get =
+        strict::StrictBuilder::request(Vec::new(), Method::Get, Version::Http11, Uri::asterisk());
+    assert_eq!(get.err(), Some(Error::InvalidRequestTarget));
+
+    Ok(())
+}
+
+#[test]
+fn form_body() -> Result<()> {
+    let mut buf = Vec::new();
+    let mut req = request::post(&mut buf, Uri::new(b"/login"))?;
+    req.header("Host", "example.com")?;
+    req.form([("user name", "a&b=c"), ("pass", "100% ∑"), ("ok", "*-._~+")])?;
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"POST /login HTTP/1.1\r\n\
+              Host: example.com\r\n\
+              Content-Type: application/x-www-form-urlencoded\r\n\
+              Content-Length: 55\r\n\
+              \r\n\
+              user+name=a%26b%3Dc&pass=100%25+%E2%88%91&ok=*-._%7E%2B"
+        )
+    );
+
+    // There's room for the headers but not for the body.
+    let mut out = [0u8; 100];
+    let mut slice = SliceBuf::new(&mut out);
+    let req = request::post(&mut slice, Uri::new(b"/"))?;
+    assert_eq!(
+        req.form([("key", "a long value that doesn't fit")]).err(),
+        Some(Error::OutOfBuffer)
+    );
+    assert_eq!(escaped(slice.filled()), escaped(b"POST / HTTP/1.1\r\n"));
+
+    Ok(())
+}
+
+#[test]
+fn multipart_parts() -> Result<()> {
+    use crate::multipart::{content_length, Boundary, MultipartWriter, Part};
+
+    let boundary = Boundary::new("b")?;
+    let part = Part::new("doc").filename("résumé \"1\".pdf");
+
+    let mut buf = Vec::new();
+    let req = request::post(&mut buf, Uri::new(b"/"))?;
+    let mut writer = MultipartWriter::new(req, &boundary)?;
+    writer.part(&part)?;
+    writer.write(&mut &b"ab"[..])?.write(&mut &b"c"[..])?;
+    writer.finish()?;
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"POST / HTTP/1.1\r\n\
+              Content-Type: multipart/form-data; boundary=\"b\"\r\n\
+              \r\n\
+              --b\r\n\
+              Content-Disposition: form-data; name=\"doc\"; filename=\"r_sum_ \\\"1\\\".pdf\"; \
+              filename*=UTF-8''r%C3%A9sum%C3%A9%20%221%22.pdf\r\n\
+              \r\n\
+              abc\r\n\
+              --b--\r\n"
+        )
+    );
+
+    let parts = [(part, 3)];
+    let body = buf.len() - buf.windows(4).position(|w| w == b"\r\n\r\n").unwrap() - 4;
+    assert_eq!(content_length(&boundary, &parts)?, body);
+
+    let req = request::post(Vec::new(), Uri::new(b"/"))?;
+    let mut writer = MultipartWriter::sized(req, &boundary, &parts)?;
+    writer.part(&part)?.write(&mut &b"ab"[..])?;
+    assert_eq!(writer.finish().err(), Some(Error::BodyLengthMismatch));
+
+    // Long non-ASCII filenames are written out in full.
+    let filename = "\u{e9}".repeat(300);
+    let mut buf = Vec::new();
+    let req = request::post(&mut buf, Uri::new(b"/"))?;
+    MultipartWriter::new(req, &boundary)?.part(&Part::new("f").filename(&filename))?;
+    let fallback = format!("; filename=\"{}\";", "_".repeat(300));
+    assert!(buf.windows(fallback.len()).any(|w| w == fallback.as_bytes()));
+
+    assert_eq!(
+        Boundary::new("trailing ").err(),
+        Some(Error::InvalidBoundary)
+    );
+    assert_ne!(Boundary::random().as_str(), Boundary::random().as_str());
+
+    Ok(())
+}
+
+#[test]
+fn cookies() -> Result<()> {
+    use crate::headers::{Cookie, SameSite, SetCookie};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let expires = HttpDate::from(UNIX_EPOCH + Duration::from_secs(784111777));
+    let cookie = SetCookie::new("sid", "\"abc\"")
+        .expires(expires)
+        .domain("example.com")
+        .same_site(SameSite::None)
+        .secure()
+        .partitioned();
+
+    let mut buf = Vec::new();
+    response::ok(&mut buf)?.header("Set-Cookie", cookie)?;
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"HTTP/1.1 200 OK\r\n\
+              Set-Cookie: sid=\"abc\"; Expires=Sun, 06 Nov 1994 08:49:37 GMT; \
+              Domain=example.com; Secure; SameSite=None; Partitioned\r\n"
+        )
+    );
+
+    let mut res = response::ok(Vec::new())?;
+    for cookie in [
+        SetCookie::new("bad name", "v"),
+        SetCookie::new("n", "a;b"),
+        SetCookie::new("n", "a b"),
+        SetCookie::new("n", "v").path("/a;b"),
+    ] {
+        assert_eq!(
+            res.header("Set-Cookie", cookie).err(),
+            Some(Error::InvalidHeaderValue)
+        );
+    }
+
+    assert_eq!(
+        res.header("Cookie", Cookie::new(&[("a", "b,c")])).err(),
+        Some(Error::InvalidHeaderValue)
+    );
+    assert_eq!(
+        res.header("Cookie", Cookie::new(&[])).err(),
+        Some(Error::InvalidHeaderValue)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn date_formatting() {
+    use crate::headers::Date;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    for &secs in &[
+        0,
+        784111777,
+        951782400,
+        1709164800,
+        4102444799,
+        253402300799,
+    ] {
+        let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs));
+        let expected = date.to_string();
+        assert_eq!(escaped(Date::from_unix_seconds(secs).as_bytes()), expected);
+
+        let mut buf = Vec::new();
+        assert!(date.put(&mut buf).is_ok());
+        assert_eq!(escaped(&buf), expected);
+    }
+}
+
+#[test]
+fn date_cache_uses_clock() -> Result<()> {
+    use crate::headers::DateCache;
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let now = Cell::new(784111777);
+    let cache = DateCache::new(|| now.get());
+    assert_eq!(
+        escaped(cache.now().as_bytes()),
+        "Sun, 06 Nov 1994 08:49:37 GMT"
+    );
+    now.set(784111778);
+    assert_eq!(
+        escaped(cache.now().as_bytes()),
+        "Sun, 06 Nov 1994 08:49:38 GMT"
+    );
+
+    let now = AtomicU64::new(0);
+    let cache = DateCache::new(|| now.load(Ordering::Relaxed));
+    let mut buf = Vec::new();
+    response::ok(&mut buf)?.header("Date", cache.now())?;
+    assert_eq!(
+        escaped(&buf),
+        escaped(b"HTTP/1.1 200 OK\r\nDate: Thu, 01 Jan 1970 00:00:00 GMT\r\n")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn date_cache_is_shared_between_threads() {
+    use crate::headers::{Date, DateCache};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let now = AtomicU64::new(784111777);
+    let cache = DateCache::new(|| now.load(Ordering::Relaxed));
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..10_000 {
+                    let before = now.load(Ordering::Relaxed);
+                    let date = cache.now();
+                    let after = now.load(Ordering::Relaxed);
+
+                    // The date must never be torn by a concurrent update.
+                    assert!((before..=after).any(|secs| date == Date::from_unix_seconds(secs)));
+                }
+            });
+        }
+
+        for _ in 0..1000 {
+            now.fetch_add(1, Ordering::Relaxed);
+            std::thread::yield_now();
+        }
+    });
+}
+
+#[test]
+fn multipart_byteranges() -> Result<()> {
+    use crate::headers::{AcceptRanges, ByteRange};
+    use crate::multipart::{Boundary, ByteRangesWriter};
+
+    assert_eq!(ByteRange::FromTo(5, 100).resolve(10), Some((5, 9)));
+    assert_eq!(ByteRange::From(10).resolve(10), None);
+    assert_eq!(ByteRange::Last(20).resolve(10), Some((0, 9)));
+    assert_eq!(ByteRange::Last(0).resolve(10), None);
+
+    let body = b"0123456789";
+    let ranges = [(0, 1), (8, 9)];
+    let boundary = Boundary::new("b")?;
+
+    let mut buf = Vec::new();
+    let mut res = response::partial_content(&mut buf)?;
+    res.header("Accept-Ranges", AcceptRanges::Bytes)?;
+    let mut writer = ByteRangesWriter::new(res, &boundary, None, &ranges, 10)?;
+    assert_eq!(
+        writer.range(&mut &body[..3]).err(),
+        Some(Error::BodyLengthMismatch)
+    );
+    for &(first, last) in &ranges {
+        writer.range(&mut &body[first as usize..=last as usize])?;
+    }
+    writer.finish()?;
+
+    let (_, body) = buf.split_at(buf.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4);
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"HTTP/1.1 206 Partial Content\r\n\
+              Accept-Ranges: bytes\r\n\
+              Content-Type: multipart/byteranges; boundary=\"b\"\r\n\
+              Content-Length: 87\r\n\
+              \r\n\
+              --b\r\n\
+              Content-Range: bytes 0-1/10\r\n\
+              \r\n\
+              01\r\n\
+              --b\r\n\
+              Content-Range: bytes 8-9/10\r\n\
+              \r\n\
+              89\r\n\
+              --b--\r\n"
+        )
+    );
+    assert_eq!(body.len(), 87);
+
+    let res = response::partial_content(Vec::new())?;
+    assert_eq!(
+        ByteRangesWriter::new(res, &boundary, None, &[(5, 10)], 10).err(),
+        Some(Error::UnsatisfiableRange)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn content_negotiation() -> Result<()> {
+    use crate::headers::{
+        AcceptCharset, AcceptEncoding, AcceptLanguage, MediaRange, QItem, QValue,
+    };
+
+    let q = |q| QValue::from_thousandths(q).unwrap();
+    assert_eq!(QValue::from_thousandths(1001), None);
+
+    let mut buf = Vec::new();
+    let mut req = request::get(&mut buf, Uri::new(b"/"))?;
+    req.header(
+        "Accept",
+        headers::Accept::new(&[MediaRange::new("text", "html")
+            .params(&[("level", "1"), ("charset", "a b")])
+            .q(q(125))]),
+    )?;
+    req.header(
+        "Accept-Language",
+        AcceptLanguage::new(&[QItem::new("ja"), QItem::new("en-US").q(q(700))]),
+    )?;
+    req.header(
+        "Accept-Encoding",
+        AcceptEncoding::new(&[QItem::new("gzip").q(q(1)), QItem::new("br").q(QValue::ZERO)]),
+    )?;
+    req.header(
+        "Accept-Charset",
+        AcceptCharset::new(&[QItem::new("utf-8").q(q(10))]),
+    )?;
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"GET / HTTP/1.1\r\n\
+              Accept: text/html;level=1;charset=\"a b\";q=0.125\r\n\
+              Accept-Language: ja, en-US;q=0.7\r\n\
+              Accept-Encoding: gzip;q=0.001, br;q=0\r\n\
+              Accept-Charset: utf-8;q=0.01\r\n"
+        )
+    );
+
+    let mut req = request::get(Vec::new(), Uri::new(b"/"))?;
+    for range in [
+        MediaRange::new("*", "html"),
+        MediaRange::new("text", "a b"),
+        MediaRange::new("text", "html").params(&[("q", "1")]),
+    ] {
+        assert_eq!(
+            req.header("Accept", headers::Accept::new(&[range])).err(),
+            Some(Error::InvalidHeaderValue)
+        );
+    }
+    assert_eq!(
+        req.header(
+            "Accept-Language",
+            AcceptLanguage::new(&[QItem::new("englishlanguage")])
+        )
+        .err(),
+        Some(Error::InvalidHeaderValue)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn entity_tags() -> Result<()> {
+    use crate::headers::{EntityTag, EntityTags, IfRange};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(784111777));
+
+    let mut buf = Vec::new();
+    request::get(&mut buf, Uri::new(b"/"))?
+        .header("If-Match", EntityTags::List(&[EntityTag::strong("")]))?
+        .header("If-Range", IfRange::Tag(EntityTag::strong("v1")))?
+        .header("If-Modified-Since", date)?;
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"GET / HTTP/1.1\r\n\
+              If-Match: \"\"\r\n\
+              If-Range: \"v1\"\r\n\
+              If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"
+        )
+    );
+
+    assert!(EntityTag::weak("a").weak_eq(&EntityTag::strong("a")));
+    assert!(!EntityTag::weak("a").strong_eq(&EntityTag::strong("a")));
+
+    // obs-text is allowed within an entity tag, DEL is not.
+    assert!(EntityTag::strong("caf\u{e9}").validate());
+    assert!(!EntityTag::strong("a\x7f").validate());
+
+    let mut req = request::get(Vec::new(), Uri::new(b"/"))?;
+    assert_eq!(
+        req.header("ETag", EntityTag::strong("a\"b")).err(),
+        Some(Error::InvalidHeaderValue)
+    );
+    assert_eq!(
+        req.header("If-None-Match", EntityTags::List(&[])).err(),
+        Some(Error::InvalidHeaderValue)
+    );
+    assert_eq!(
+        req.header("If-Range", IfRange::Tag(EntityTag::weak("v1")))
+            .err(),
+        Some(Error::InvalidHeaderValue)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn conditional_precedence() -> Result<()> {
+    use crate::conditional::{Outcome, Preconditions, Validators};
+    use crate::decode::Header;
+    use crate::headers::{EntityTag, EntityTags};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let date = |secs| HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs));
+    let strong = [EntityTag::strong("v1")];
+    let weak = [EntityTag::weak("v1")];
+    let other = [EntityTag::strong("v0")];
+    let current = Validators::new()
+        .etag(EntityTag::strong("v1"))
+        .last_modified(date(1000));
+
+    let cases = [
+        (Preconditions::new(), Method::Get, Outcome::Continue),
+        // If-Match uses the strong comparison.
+        (
+            Preconditions::new().if_match(EntityTags::List(&strong)),
+            Method::Put,
+            Outcome::Continue,
+        ),
+        (
+            Preconditions::new().if_match(EntityTags::List(&weak)),
+            Method::Put,
+            Outcome::PreconditionFailed,
+        ),
+        // If-Unmodified-Since is ignored when If-Match is present.
+        (
+            Preconditions::new()
+                .if_match(EntityTags::Any)
+                .if_unmodified_since(date(500)),
+            Method::Put,
+            Outcome::Continue,
+        ),
+        (
+            Preconditions::new().if_unmodified_since(date(500)),
+            Method::Put,
+            Outcome::PreconditionFailed,
+        ),
+        // If-None-Match uses the weak comparison.
+        (
+            Preconditions::new().if_none_match(EntityTags::List(&weak)),
+            Method::Head,
+            Outcome::NotModified,
+        ),
+        (
+            Preconditions::new().if_none_match(EntityTags::Any),
+            Method::Post,
+            Outcome::PreconditionFailed,
+        ),
+        // If-Modified-Since is ignored when If-None-Match is present, and
+        // for methods other than GET and HEAD.
+        (
+            Preconditions::new()
+                .if_none_match(EntityTags::List(&other))
+                .if_modified_since(date(2000)),
+            Method::Get,
+            Outcome::Continue,
+        ),
+        (
+            Preconditions::new().if_modified_since(date(1000)),
+            Method::Get,
+            Outcome::NotModified,
+        ),
+        (
+            Preconditions::new().if_modified_since(date(1000)),
+            Method::Put,
+            Outcome::Continue,
+        ),
+        (
+            Preconditions::new().if_modified_since(date(999)),
+            Method::Get,
+            Outcome::Continue,
+        ),
+    ];
+    for (preconditions, method, outcome) in cases.iter() {
+        assert_eq!(preconditions.evaluate(*method, &current), *outcome);
+    }
+
+    let missing = Validators::missing();
+    let any = Preconditions::new().if_match(EntityTags::Any);
+    assert_eq!(
+        any.evaluate(Method::Put, &missing),
+        Outcome::PreconditionFailed
+    );
+
+    let headers = [
+        Header {
+            name: b"If-None-Match",
+            value: b"\"v0\", ,W/\"v1\"",
+        },
+        Header {
+            name: b"If-Modified-Since",
+            value: b"not a date",
+        },
+    ];
+    let parsed = Preconditions::from_headers(&headers)?;
+    assert_eq!(parsed.evaluate(Method::Get, &current), Outcome::NotModified);
+    assert_eq!(parsed.evaluate(Method::Get, &missing), Outcome::Continue);
+
+    let invalid = [Header {
+        name: b"if-match",
+        value: b"v1",
+    }];
+    assert_eq!(
+        Preconditions::from_headers(&invalid).err(),
+        Some(Error::InvalidHeaderValue)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn conditional_responses() -> Result<()> {
+    use crate::conditional::{Conditional, Preconditions, Validators};
+    use crate::headers::{EntityTag, EntityTags};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let last_modified = HttpDate::from(UNIX_EPOCH + Duration::from_secs(784111777));
+    let current = Validators::new().last_modified(last_modified);
+    let preconditions = Preconditions::new().if_modified_since(last_modified);
+
+    let mut buf = Vec::new();
+    match preconditions.respond(&mut buf, Method::Get, &current)? {
+        Conditional::NotModified(mut res) => {
+            res.header("Content-Type", "text/html")?
+                .header("Transfer-Encoding", "chunked")?
+                .header("Last-Modified", last_modified)?
+                .header("cache-control", "max-age=60")?
+                .header("Vary", "Accept")?;
+            res.finish()?;
+        }
+        res => panic!("unexpected response {:?}", res),
+    }
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"HTTP/1.1 304 Not Modified\r\n\
+              Last-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n\
+              cache-control: max-age=60\r\n\
+              Vary: Accept\r\n\r\n"
+        )
+    );
+
+    let other = [EntityTag::strong("a")];
+    let preconditions = Preconditions::new().if_match(EntityTags::List(&other));
+    match preconditions.respond(Vec::new(), Method::Delete, &current)? {
+        Conditional::PreconditionFailed(res) => assert_eq!(
+            escaped(&res.finish()?),
+            escaped(b"HTTP/1.1 412 Precondition Failed\r\n\r\n")
+        ),
+        res => panic!("unexpected response {:?}", res),
+    }
+
+    match Preconditions::new().respond(Vec::new(), Method::Get, &current)? {
+        Conditional::Continue(buf) => assert!(buf.is_empty()),
+        res => panic!("unexpected response {:?}", res),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn cache_control() -> Result<()> {
+    use crate::headers::CacheControl;
+
+    let mut buf = Vec::new();
+    let mut req = request::get(&mut buf, Uri::new(b"/"))?;
+    req.header(
+        "Cache-Control",
+        CacheControl::new()
+            .max_stale(None)
+            .only_if_cached()
+            .min_fresh(0),
+    )?;
+    req.header(
+        "Cache-Control",
+        CacheControl::new()
+            .extensions(&[
+                ("community", Some("UCI")),
+                ("ext", Some("a b")),
+                ("x", None),
+            ])
+            .private_fields(&["Set-Cookie", "Vary"])
+            .immutable()
+            .stale_if_error(u64::MAX),
+    )?;
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"GET / HTTP/1.1\r\n\
+              Cache-Control: max-stale, min-fresh=0, only-if-cached\r\n\
+              Cache-Control: private=\"Set-Cookie, Vary\", immutable, \
+              stale-if-error=18446744073709551615, community=UCI, ext=\"a b\", x\r\n"
+        )
+    );
+
+    let mut req = request::get(Vec::new(), Uri::new(b"/"))?;
+    for invalid in [
+        CacheControl::new(),
+        CacheControl::new().no_cache_fields(&["a b"]),
+        CacheControl::new().extensions(&[("ext", Some("\r\n"))]),
+    ] {
+        assert_eq!(
+            req.header("Cache-Control", invalid).err(),
+            Some(Error::InvalidHeaderValue)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn authorization() -> Result<()> {
+    use crate::headers::{
+        Authorization, Challenge, ProxyAuthenticate, ProxyAuthorization, WwwAuthenticate,
+    };
+
+    let basic = |username, password| Authorization::Basic { username, password };
+
+    let mut buf = Vec::new();
+    request::get(&mut buf, Uri::new(b"/"))?
+        .header("Authorization", basic("", ""))?
+        .header("Authorization", basic("a", ""))?
+        .header("Authorization", basic("ab", ""))?
+        .header("Authorization", basic("", "pässwörd"))?
+        .header("Authorization", Authorization::Bearer("mF_9.B5f-4.1JqM=="))?
+        .header(
+            "Proxy-Authorization",
+            ProxyAuthorization::Params {
+                scheme: "Custom",
+                params: &[("id", "1"), ("sig", "a b")],
+            },
+        )?;
+
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"GET / HTTP/1.1\r\n\
+              Authorization: Basic Og==\r\n\
+              Authorization: Basic YTo=\r\n\
+              Authorization: Basic YWI6\r\n\
+              Authorization: Basic OnDDpHNzd8O2cmQ=\r\n\
+              Authorization: Bearer mF_9.B5f-4.1JqM==\r\n\
+              Proxy-Authorization: Custom id=1, sig=\"a b\"\r\n"
+        )
+    );
+
+    let mut buf = Vec::new();
+    let challenges = [Challenge::new("Negotiate").token68("YII=")];
+    response::unauthorized(&mut buf)?
+        .header("WWW-Authenticate", WwwAuthenticate::new(&challenges))?;
+    assert!(buf.ends_with(b"WWW-Authenticate: Negotiate YII=\r\n"));
+
+    let mut req = request::get(Vec::new(), Uri::new(b"/"))?;
+    assert_eq!(
+        req.header("Authorization", basic("a:b", "c")).err(),
+        Some(Error::InvalidHeaderValue)
+    );
+    assert_eq!(
+        req.header("Authorization", Authorization::Bearer("a=b"))
+            .err(),
+        Some(Error::InvalidHeaderValue)
+    );
+    let challenges = [Challenge::basic("x").token68("abc")];
+    assert_eq!(
+        req.header("Proxy-Authenticate", ProxyAuthenticate::new(&challenges))
+            .err(),
+        Some(Error::InvalidHeaderValue)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn digest_auth_rfc7616() -> Result<()> {
+    use crate::headers::{DigestAlgorithm, DigestAuth, DigestChallenge};
+
+    // The challenges and responses from RFC7616 section 3.9.1.
+    let sha256: &[u8] = b"Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+        algorithm=SHA-256, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+        opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"";
+    let md5: &[u8] = b"Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+        algorithm=MD5, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+        opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"";
+
+    let cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+    let uri = Uri::new(b"/dir/index.html");
+
+    for (challenge, algorithm, response) in [
+        (md5, "MD5", "8ca523f5e9506fed4657c9700eebdbec"),
+        (
+            sha256,
+            "SHA-256",
+            "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1",
+        ),
+    ] {
+        let challenge = DigestChallenge::parse(challenge)?;
+        let mut auth = DigestAuth::new(challenge, "Mufasa", "Circle of Life");
+
+        let mut buf = Vec::new();
+        let authorization = auth.authorize(Method::Get, uri, cnonce, None)?;
+        request::get(&mut buf, uri)?.header("Authorization", authorization)?;
+
+        let expected = format!(
+            "GET /dir/index.html HTTP/1.1\r\n\
+             Authorization: Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+             uri=\"/dir/index.html\", algorithm={}, \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", nc=00000001, \
+             cnonce=\"{}\", qop=auth, response=\"{}\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"\r\n",
+            algorithm, cnonce, response
+        );
+        assert_eq!(escaped(&buf), escaped(expected.as_bytes()));
+        assert_eq!(auth.nonce_count(), 1);
+    }
+
+    // Both challenges together, where the first supported one is used.
+    let both = [sha256, b", ", md5].concat();
+    assert_eq!(
+        DigestChallenge::parse(&both)?.algorithm(),
+        DigestAlgorithm::Sha256
+    );
+
+    // The same challenge restricted to auth-int, with a hashed username.
+    let challenge = DigestChallenge::parse(
+        b"Digest realm=\"http-auth@example.org\", qop=\"auth-int\", algorithm=SHA-256, \
+          nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+          opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\", userhash=true",
+    )?;
+    let mut auth = DigestAuth::new(challenge, "Mufasa", "Circle of Life");
+    let authorization = auth.authorize(Method::Post, uri, cnonce, Some(b"hello"))?;
+    let mut buf = Vec::new();
+    authorization.put(&mut buf)?;
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"Digest \
+              username=\"a947aad205e80e429958a387394944c6b496301e79f89d35a4cc23b6ee12b5b6\", \
+              realm=\"http-auth@example.org\", uri=\"/dir/index.html\", algorithm=SHA-256, \
+              nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", nc=00000001, \
+              cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", qop=auth-int, \
+              response=\"c98b95dbdb463c4483e324bced57d591946a6f84098142757b1333c52c47d62e\", \
+              opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\", userhash=true"
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn digest_auth_challenges() -> Result<()> {
+    use crate::headers::{DigestAlgorithm, DigestAuth, DigestChallenge};
+
+    // Other schemes, including token68 challenges, are skipped along with
+    // Digest challenges using unsupported algorithms.
+    let challenge = DigestChallenge::parse(
+        b"Negotiate YII=, Basic realm=\"a\\\"b\", \
+          Digest realm=x, nonce=n, algorithm=SHA-512-256, \
+          Digest realm=\"a\\\"b\", nonce=\"n\", algorithm=md5-sess, qop=auth-int",
+    )?;
+    assert_eq!(challenge.algorithm(), DigestAlgorithm::Md5Sess);
+
+    let mut auth = DigestAuth::new(challenge, "u", "p");
+    let uri = Uri::new(b"/");
+    assert_eq!(
+        auth.authorize(Method::Post, uri, "c", None).err(),
+        Some(Error::UnsupportedChallenge)
+    );
+    auth.authorize(Method::Post, uri, "c", Some(b"body"))?;
+    let authorization = auth.authorize(Method::Post, uri, "c", Some(b"body"))?;
+    let mut buf = Vec::new();
+    authorization.put(&mut buf)?;
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"Digest username=\"u\", realm=\"a\\\"b\", uri=\"/\", algorithm=MD5-sess, \
+              nonce=\"n\", nc=00000002, cnonce=\"c\", qop=auth-int, \
+              response=\"af8c72a73d3521ee25755202713dcb0a\""
+        )
+    );
+
+    // A new nonce resets the nonce count.
+    auth.update_challenge(DigestChallenge::parse(b"Digest realm=r, nonce=m")?);
+    assert_eq!(auth.nonce_count(), 0);
+
+    // Challenges without a qop use the RFC2069 scheme.
+    let authorization = auth.authorize(Method::Get, uri, "c", None)?;
+    let mut buf = Vec::new();
+    authorization.put(&mut buf)?;
+    assert_eq!(
+        escaped(&buf),
+        escaped(
+            b"Digest username=\"u\", realm=\"r\", uri=\"/\", algorithm=MD5, nonce=\"m\", \
+              response=\"823e81af1781fd11e26e916b824ea72d\""
+        )
+    );
+
+    for invalid in [&b"Digest realm=\"x"[..], b"Digest realm=x y", b"Digest, =x"] {
+        assert_eq!(
+            DigestChallenge::parse(invalid).err(),
+            Some(Error::InvalidHeaderValue)
+        );
+    }
+    assert_eq!(
+        DigestChallenge::parse(b"Basic realm=x").err(),
+        Some(Error::UnsupportedChallenge)
+    );
+
+    Ok(())
+}
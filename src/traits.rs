@@ -1,7 +1,7 @@
 use arrayvec::ArrayVec;
 use bytes::BufMut;
 
-use core::fmt;
+use crate::Counter;
 
 pub struct OutOfBufferError;
 
@@ -12,9 +12,67 @@ pub trait HeaderValue {
         true
     }
 
+    /// A cheap estimate of the number of bytes that [`put`](Self::put)
+    /// will write, if one is known. Unlike
+    /// [`encoded_len`](Self::encoded_len) this never formats the value.
     fn est_len(&self) -> Option<usize> {
         None
     }
+
+    /// The exact number of bytes that [`put`](Self::put) will write.
+    fn encoded_len(&self) -> usize {
+        let mut counter = Counter::new();
+        let _ = self.put(&mut counter);
+
+        counter.count()
+    }
+
+    /// Write the value into a buffer that is already known to have at
+    /// least [`encoded_len`](Self::encoded_len) bytes remaining.
+    ///
+    /// Values that measure themselves to keep `put` atomic can skip doing
+    /// so here. By default this is the same as `put`.
+    fn put_measured<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
+        self.put(buf)
+    }
+}
+
+// Header values that are written out piece by piece, where a failed
+// write may leave part of the value in the buffer.
+//
+// `HeaderValue` is implemented for them by measuring the value once with
+// a `Counter` before writing it out, so that `put` is still atomic.
+pub(crate) trait WriteValue {
+    fn write<B: BufMut>(&self, buf: &mut B) -> crate::Result<()>;
+
+    fn is_valid(&self) -> bool {
+        true
+    }
+}
+
+impl<V: WriteValue> HeaderValue for V {
+    fn put<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
+        if buf.remaining_mut() < self.encoded_len() {
+            return Err(OutOfBufferError);
+        }
+
+        self.put_measured(buf)
+    }
+
+    fn validate(&self) -> bool {
+        self.is_valid()
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut counter = Counter::new();
+        let _ = self.write(&mut counter);
+
+        counter.count()
+    }
+
+    fn put_measured<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
+        self.write(buf).map_err(|_| OutOfBufferError)
+    }
 }
 
 impl HeaderValue for &'_ [u8] {
@@ -35,6 +93,10 @@ impl HeaderValue for &'_ [u8] {
     fn est_len(&self) -> Option<usize> {
         Some(self.len())
     }
+
+    fn encoded_len(&self) -> usize {
+        self.len()
+    }
 }
 
 impl HeaderValue for &'_ str {
@@ -49,10 +111,55 @@ impl HeaderValue for &'_ str {
     fn est_len(&self) -> Option<usize> {
         Some(self.len())
     }
+
+    fn encoded_len(&self) -> usize {
+        self.len()
+    }
+}
+
+impl HeaderValue for crate::Uri<'_> {
+    fn put<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
+        crate::util::write_uri(buf, *self).map_err(|_| OutOfBufferError)
+    }
+
+    fn validate(&self) -> bool {
+        match self.parts() {
+            Some(parts) => crate::uri::validate_parts(&parts),
+            None => self.as_bytes().is_some_and(|uri| !uri.is_empty()),
+        }
+    }
+}
+
+// Forwards to a borrowed header value, for when a value has to be put
+// more than once.
+#[cfg(feature = "std")]
+pub(crate) struct ByRef<'a, V>(pub(crate) &'a V);
+
+#[cfg(feature = "std")]
+impl<V: HeaderValue> HeaderValue for ByRef<'_, V> {
+    fn put<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
+        self.0.put(buf)
+    }
+
+    fn validate(&self) -> bool {
+        self.0.validate()
+    }
+
+    fn est_len(&self) -> Option<usize> {
+        self.0.est_len()
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.0.encoded_len()
+    }
+
+    fn put_measured<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
+        self.0.put_measured(buf)
+    }
 }
 
 const fn base10_digits<T>(signed: bool) -> usize {
-    (std::mem::size_of::<T>() * 8 + 2) / 3 + (signed as usize)
+    (core::mem::size_of::<T>() * 8).div_ceil(3) + (signed as usize)
 }
 
 fn reverse_in_place<T>(mut slice: &mut [T]) {
@@ -61,10 +168,21 @@ fn reverse_in_place<T>(mut slice: &mut [T]) {
         let (last, rest) = rest.split_last_mut().unwrap();
         slice = rest;
 
-        std::mem::swap(first, last);
+        core::mem::swap(first, last);
     }
 }
 
+fn decimal_len(mut value: u128) -> usize {
+    let mut len = 1;
+
+    while value >= 10 {
+        value /= 10;
+        len += 1;
+    }
+
+    len
+}
+
 macro_rules! impl_unsigned {
     ( $( $ty:ident ),* ) => {
         $(
@@ -92,6 +210,10 @@ macro_rules! impl_unsigned {
 
                     Ok(())
                 }
+
+                fn encoded_len(&self) -> usize {
+                    decimal_len(*self as u128)
+                }
             }
         )*
     }
@@ -134,6 +256,10 @@ macro_rules! impl_signed {
 
                     Ok(())
                 }
+
+                fn encoded_len(&self) -> usize {
+                    (*self < 0) as usize + decimal_len(self.unsigned_abs() as u128)
+                }
             }
         )*
     }
@@ -142,28 +268,33 @@ macro_rules! impl_signed {
 impl_unsigned!(usize, u8, u16, u32, u64, u128);
 impl_signed!(isize, i8, i16, i32, i64, i128);
 
-pub(crate) struct BufferFmt<'b, B: BufMut>(pub &'b mut B);
-
-impl<'b, B: BufMut> fmt::Write for BufferFmt<'b, B> {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        s.put(self.0).map_err(|_| fmt::Error)
-    }
-}
-
 #[cfg(feature = "httpdate")]
 mod httpdate {
     use super::*;
+    use crate::headers::Date;
     use crate::HttpDate;
 
-    use std::fmt::Write;
+    use std::time::{SystemTime, UNIX_EPOCH};
 
+    // Formatted the same way as the `Date` header value, instead of going
+    // through `fmt::Display`.
     impl HeaderValue for HttpDate {
         fn put<B: BufMut>(&self, buf: &mut B) -> Result<(), OutOfBufferError> {
-            write!(&mut BufferFmt(buf), "{}", self).map_err(|_| OutOfBufferError)
+            let seconds = SystemTime::from(*self)
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+
+            Date::from_unix_seconds(seconds).put(buf)
         }
 
         fn est_len(&self) -> Option<usize> {
-            Some(30)
+            Some(self.encoded_len())
+        }
+
+        fn encoded_len(&self) -> usize {
+            // IMF-fixdate is always 29 bytes long, e.g.
+            // "Sun, 06 Nov 1994 08:49:37 GMT".
+            29
         }
     }
 }
@@ -173,7 +304,7 @@ mod tests {
     use super::*;
     use std::borrow::Cow;
 
-    fn escaped(bytes: &[u8]) -> Cow<str> {
+    fn escaped(bytes: &[u8]) -> Cow<'_, str> {
         if bytes.iter().copied().all(|c| c.is_ascii()) {
             Cow::Borrowed(std::str::from_utf8(bytes).unwrap())
         } else {
@@ -204,6 +335,12 @@ mod tests {
                     "test for {} failed",
                     stringify!($value)
                 );
+                assert_eq!(
+                    value.encoded_len(),
+                    buf.len(),
+                    "encoded_len for {} failed",
+                    stringify!($value)
+                );
             });
 
             if let Err(e) = res {
@@ -216,9 +353,10 @@ mod tests {
     macro_rules! standard_tests {
         ($( $ty:ident ),*) => {
             $(
-                test_put!(std::$ty::MAX);
-                test_put!(std::$ty::MIN);
+                test_put!($ty::MAX);
+                test_put!($ty::MIN);
                 test_put!(0 as $ty);
+                test_put!(10 as $ty);
             )*
         }
     }
@@ -233,4 +371,26 @@ mod tests {
         assert!(base10_digits::<u8>(false) >= 3);
         assert!(base10_digits::<i8>(true) >= 4);
     }
+
+    #[test]
+    fn est_len_is_cheap() {
+        assert_eq!("text/plain".est_len(), Some(10));
+        assert_eq!(1234u32.est_len(), None);
+        assert_eq!(
+            crate::headers::CacheControl::new().no_store().est_len(),
+            None
+        );
+    }
+
+    #[test]
+    fn counter_rewinds() {
+        let mut counter = Counter::new();
+        "abc".put(&mut counter).ok().unwrap();
+        let checkpoint = crate::Rewind::checkpoint(&counter);
+        12345u32.put(&mut counter).ok().unwrap();
+        assert_eq!(counter.count(), 8);
+
+        crate::Rewind::rewind(&mut counter, checkpoint);
+        assert_eq!(counter.count(), 3);
+    }
 }
@@ -0,0 +1,185 @@
+use bytes::BufMut;
+
+use crate::traits::WriteValue;
+use crate::util::{try_write, write_percent_escaped};
+use crate::{Error, Result, Uri, UriData};
+
+/// Builder for an origin-form request target assembled from its
+/// components.
+///
+/// Each component is escaped using the character set that RFC 3986 allows
+/// for it, so a `/` within a path segment or a `&` within a query value is
+/// percent-encoded instead of changing the structure of the URI. Nothing is
+/// allocated, the components are escaped as they are written out.
+///
+/// The fragment is never sent as part of a request target so it is
+/// stripped when the builder is converted into a [`Uri`]. It is only
+/// written out when the builder is used directly as a header value, such as
+/// for a `Location` header.
+///
+/// ```
+/// # use httpencode::*;
+/// # fn main() -> Result<(), Error> {
+/// let uri = UriBuilder::new()
+///     .segments(&["files", "a/b c"])
+///     .query(&[("q", "x&y=z")])
+///     .fragment("top");
+///
+/// let mut buf = Vec::new();
+/// HttpBuilder::request(&mut buf, Method::Get, Version::Http11, uri.build())?
+///     .header("Content-Location", uri)?;
+///
+/// assert_eq!(
+///     buf,
+///     &b"GET /files/a%2Fb%20c?q=x%26y%3Dz HTTP/1.1\r\n\
+///        Content-Location: /files/a%2Fb%20c?q=x%26y%3Dz#top\r\n"[..]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UriBuilder<'a> {
+    segments: &'a [&'a str],
+    query: &'a [(&'a str, &'a str)],
+    fragment: Option<&'a str>,
+}
+
+impl<'a> UriBuilder<'a> {
+    /// Create a builder for the root path `/`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the path segments. Each segment is preceded by a `/`.
+    ///
+    /// Dot-segments are not allowed since they would move up or stay at
+    /// the same level of the path, even once percent-encoded. Writing out a
+    /// `.` or `..` segment fails with [`Error::InvalidUri`].
+    ///
+    /// [`Error::InvalidUri`]: crate::Error::InvalidUri
+    pub fn segments(mut self, segments: &'a [&'a str]) -> Self {
+        self.segments = segments;
+        self
+    }
+
+    /// Set the query parameters, which are written out as
+    /// `key=value` pairs separated by `&`.
+    pub fn query(mut self, params: &'a [(&'a str, &'a str)]) -> Self {
+        self.query = params;
+        self
+    }
+
+    /// Set the fragment.
+    pub fn fragment(mut self, fragment: &'a str) -> Self {
+        self.fragment = Some(fragment);
+        self
+    }
+
+    /// Create a request target from this builder, stripping the fragment.
+    pub fn build(self) -> Uri<'a> {
+        Uri {
+            data: UriData::Parts(UriBuilder {
+                fragment: None,
+                ..self
+            }),
+        }
+    }
+}
+
+impl<'a> From<UriBuilder<'a>> for Uri<'a> {
+    fn from(builder: UriBuilder<'a>) -> Self {
+        builder.build()
+    }
+}
+
+impl WriteValue for UriBuilder<'_> {
+    fn write<B: BufMut>(&self, buf: &mut B) -> Result<()> {
+        write_parts(buf, self)
+    }
+
+    fn is_valid(&self) -> bool {
+        validate_parts(self)
+    }
+}
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+fn is_sub_delim(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'='
+    )
+}
+
+// segment = *pchar
+fn is_segment_char(byte: u8) -> bool {
+    is_unreserved(byte) || is_sub_delim(byte) || matches!(byte, b':' | b'@')
+}
+
+// query = *( pchar / "/" / "?" )
+//
+// `&`, `=`, and `+` are also escaped since they are given special meaning
+// by the key-value pairs within the query.
+fn is_query_char(byte: u8) -> bool {
+    (is_segment_char(byte) || matches!(byte, b'/' | b'?')) && !matches!(byte, b'&' | b'=' | b'+')
+}
+
+// fragment = *( pchar / "/" / "?" )
+fn is_fragment_char(byte: u8) -> bool {
+    is_segment_char(byte) || matches!(byte, b'/' | b'?')
+}
+
+// An upper bound on the length of the URI once written out by
+// `write_parts`, assuming that every byte is percent-encoded.
+pub(crate) fn max_len(parts: &UriBuilder) -> usize {
+    let segments: usize = parts.segments.iter().map(|s| 1 + s.len() * 3).sum();
+    let query: usize = parts
+        .query
+        .iter()
+        .map(|(key, val)| 2 + (key.len() + val.len()) * 3)
+        .sum();
+    let fragment = parts.fragment.map_or(0, |f| 1 + f.len() * 3);
+
+    segments.max(1) + query + fragment
+}
+
+// Whether the path is free of dot-segments. RFC3986 section 6.2.2.2
+// normalizes percent-encoded unreserved characters, so `%2E` is a
+// dot-segment as well.
+pub(crate) fn validate_parts(parts: &UriBuilder) -> bool {
+    !parts
+        .segments
+        .iter()
+        .any(|segment| matches!(*segment, "." | ".."))
+}
+
+pub(crate) fn write_parts<B: BufMut>(buf: &mut B, parts: &UriBuilder) -> Result<()> {
+    if !validate_parts(parts) {
+        return Err(Error::InvalidUri);
+    }
+
+    if parts.segments.is_empty() {
+        try_write(buf, b"/")?;
+    }
+
+    for segment in parts.segments {
+        try_write(buf, b"/")?;
+        write_percent_escaped(buf, segment.as_bytes(), is_segment_char)?;
+    }
+
+    for (i, (key, val)) in parts.query.iter().enumerate() {
+        try_write(buf, if i == 0 { b"?" } else { b"&" })?;
+        write_percent_escaped(buf, key.as_bytes(), is_query_char)?;
+        try_write(buf, b"=")?;
+        write_percent_escaped(buf, val.as_bytes(), is_query_char)?;
+    }
+
+    if let Some(fragment) = parts.fragment {
+        try_write(buf, b"#")?;
+        write_percent_escaped(buf, fragment.as_bytes(), is_fragment_char)?;
+    }
+
+    Ok(())
+}
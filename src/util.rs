@@ -1,4 +1,6 @@
-use bytes::BufMut;
+use bytes::{Buf, BufMut};
+
+use core::mem::MaybeUninit;
 
 use crate::{Error, Method, Result, Status, Uri, UriData, Version};
 
@@ -15,6 +17,66 @@ pub(crate) const CONNECT: &[u8] = b"CONNECT";
 pub(crate) const HTTP_1_0: &[u8] = b"HTTP/1.0";
 pub(crate) const HTTP_1_1: &[u8] = b"HTTP/1.1";
 
+// Something that the bytes written into a `Sink` are passed on to.
+pub(crate) trait Consume {
+    fn consume(&mut self, bytes: &[u8]);
+}
+
+impl<F: FnMut(&[u8])> Consume for F {
+    fn consume(&mut self, bytes: &[u8]) {
+        self(bytes)
+    }
+}
+
+// A buffer which passes everything written into it on to a callback
+// instead of storing it.
+#[derive(Clone)]
+pub(crate) struct Sink<F: Consume> {
+    func: F,
+    scratch: [MaybeUninit<u8>; 64],
+}
+
+impl<F: Consume> Sink<F> {
+    pub(crate) fn new(func: F) -> Self {
+        Self {
+            func,
+            scratch: [MaybeUninit::uninit(); 64],
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &F {
+        &self.func
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut F {
+        &mut self.func
+    }
+}
+
+impl<F: Consume> BufMut for Sink<F> {
+    fn remaining_mut(&self) -> usize {
+        usize::MAX
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        // Safety: The caller guarantees that the first `cnt` bytes
+        //         returned by `bytes_mut` have been initialized.
+        let bytes = &self.scratch[..cnt];
+        self.func.consume(core::slice::from_raw_parts(
+            bytes.as_ptr() as *const u8,
+            cnt,
+        ));
+    }
+
+    fn bytes_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        &mut self.scratch
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.func.consume(src)
+    }
+}
+
 pub(crate) fn try_write<B: BufMut>(buf: &mut B, bytes: &[u8]) -> Result<()> {
     if buf.remaining_mut() < bytes.len() {
         return Err(Error::OutOfBuffer);
@@ -55,19 +117,23 @@ pub(crate) fn try_write<B: BufMut>(buf: &mut B, bytes: &[u8]) -> Result<()> {
 // > they MUST be implemented with the same semantics as those specified
 // > in section 9.
 pub(crate) fn write_method<B: BufMut>(buf: &mut B, method: Method) -> Result<()> {
+    try_write(buf, method_bytes(method)?)
+}
+
+pub(crate) fn method_bytes(method: Method<'_>) -> Result<&[u8]> {
     match method {
-        Method::Options => try_write(buf, OPTIONS),
-        Method::Get => try_write(buf, GET),
-        Method::Head => try_write(buf, HEAD),
-        Method::Post => try_write(buf, POST),
-        Method::Put => try_write(buf, PUT),
-        Method::Patch => try_write(buf, PATCH),
-        Method::Delete => try_write(buf, DELETE),
-        Method::Trace => try_write(buf, TRACE),
-        Method::Connect => try_write(buf, CONNECT),
+        Method::Options => Ok(OPTIONS),
+        Method::Get => Ok(GET),
+        Method::Head => Ok(HEAD),
+        Method::Post => Ok(POST),
+        Method::Put => Ok(PUT),
+        Method::Patch => Ok(PATCH),
+        Method::Delete => Ok(DELETE),
+        Method::Trace => Ok(TRACE),
+        Method::Connect => Ok(CONNECT),
         Method::Custom(method) => {
             if validate_method(method) {
-                try_write(buf, method.as_bytes())
+                Ok(method.as_bytes())
             } else {
                 Err(Error::InvalidMethod)
             }
@@ -138,32 +204,134 @@ pub(crate) fn write_method<B: BufMut>(buf: &mut B, method: Method) -> Result<()>
 // >     hex         = digit | "A" | "B" | "C" | "D" | "E" | "F" |
 // >                           "a" | "b" | "c" | "d" | "e" | "f"
 pub(crate) fn write_uri<B: BufMut>(buf: &mut B, uri: Uri) -> Result<()> {
-    pub(crate) fn is_valid(byte: u8) -> bool {
-        // Bit-packed lookup table of valid unescaped characters.
-        //
-        // This corresponds to the characters as defined by
-        // RFC2396 section 2.
-        //
-        // Note that no byte above 128 is valid in a URI.
-        const LOOKUP: u128 = 0x47FFFFFE87FFFFFF2FFFFFD200000000;
-
-        return (byte < 128) & (((LOOKUP.wrapping_shr(byte as u32)) & 1) != 0);
-    }
-
     match uri.data {
         UriData::Unescaped(path) => {
             if path.is_empty() {
                 return Err(Error::InvalidUri);
             }
 
-            write_percent_escaped(buf, path, is_valid)
+            write_percent_escaped(buf, path, is_path_char)
         }
         UriData::Escaped(path) => try_write(buf, path),
+        UriData::Parts(parts) => crate::uri::write_parts(buf, &parts),
     }
 }
 
+// Whether `byte` may appear unescaped within the path written by
+// `write_uri`. Everything else, including "?", is percent-encoded.
+fn is_path_char(byte: u8) -> bool {
+    // Bit-packed lookup table of valid unescaped characters.
+    //
+    // This corresponds to the characters as defined by
+    // RFC2396 section 2.
+    //
+    // Note that no byte above 128 is valid in a URI.
+    const LOOKUP: u128 = 0x47FFFFFE87FFFFFF2FFFFFD200000000;
+
+    (byte < 128) & (((LOOKUP.wrapping_shr(byte as u32)) & 1) != 0)
+}
+
+// Whether `byte` may appear unescaped within an already-escaped request
+// URI. This is a path character or the "?" that separates the query.
+pub(crate) fn is_uri_char(byte: u8) -> bool {
+    is_path_char(byte) || byte == b'?'
+}
+
+// Validate an already-escaped request URI. Every character must either
+// be valid unescaped or part of an `escaped` triplet.
+pub(crate) fn validate_escaped_uri(uri: &[u8]) -> bool {
+    let mut bytes = uri;
+
+    while let Some((&c, rest)) = bytes.split_first() {
+        bytes = match (c, rest) {
+            (b'%', [hi, lo, rest @ ..]) if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                rest
+            }
+            (c, rest) if is_uri_char(c) => rest,
+            _ => return false,
+        };
+    }
+
+    !uri.is_empty()
+}
+
+// Split an authority into its host and, if present, its port.
+fn split_authority(authority: &[u8]) -> (&[u8], Option<&[u8]>) {
+    match memchr::memrchr(b':', authority) {
+        // The colon is part of an IP literal, e.g. `[::1]`.
+        Some(_) if authority.ends_with(b"]") => (authority, None),
+        Some(colon) => (&authority[..colon], Some(&authority[colon + 1..])),
+        None => (authority, None),
+    }
+}
+
+// Validate the host component of an authority. Percent-encoded host names
+// are not supported.
+fn validate_host(host: &[u8]) -> bool {
+    match host {
+        [b'[', literal @ .., b']'] => {
+            !literal.is_empty()
+                && literal
+                    .iter()
+                    .all(|&c| c.is_ascii_hexdigit() || c == b':' || c == b'.')
+        }
+        _ => {
+            !host.is_empty()
+                && host.iter().all(|&c| match c {
+                    b'/' | b'?' | b'#' | b'@' | b':' => false,
+                    c => is_uri_char(c),
+                })
+        }
+    }
+}
+
+// Whether `uri` is in the authority-form required by CONNECT requests,
+// that is a host name followed by a port number (RFC7231 section 4.3.6).
+pub(crate) fn is_authority_form(uri: &[u8]) -> bool {
+    match split_authority(uri) {
+        (host, Some(port)) => {
+            validate_host(host) && !port.is_empty() && port.iter().all(u8::is_ascii_digit)
+        }
+        (_, None) => false,
+    }
+}
+
+// Whether `uri` is in absolute-form (RFC7230 section 5.3.2). Only
+// hierarchical URIs with an authority and without a fragment or
+// userinfo are accepted.
+pub(crate) fn is_absolute_form(uri: &[u8]) -> bool {
+    let sep = match uri.windows(3).position(|w| w == b"://") {
+        Some(sep) => sep,
+        None => return false,
+    };
+    let (scheme, rest) = (&uri[..sep], &uri[sep + 3..]);
+
+    let valid_scheme = match scheme.split_first() {
+        Some((first, rest)) => {
+            first.is_ascii_alphabetic()
+                && rest
+                    .iter()
+                    .all(|&c| c.is_ascii_alphanumeric() || matches!(c, b'+' | b'-' | b'.'))
+        }
+        None => false,
+    };
+
+    let end = rest
+        .iter()
+        .position(|&c| c == b'/' || c == b'?')
+        .unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(end);
+
+    let valid_authority = match split_authority(authority) {
+        (host, Some(port)) => validate_host(host) && port.iter().all(u8::is_ascii_digit),
+        (host, None) => validate_host(host),
+    };
+
+    valid_scheme && valid_authority && (path.is_empty() || validate_escaped_uri(path))
+}
+
 // Write out a string and percent-escape any invalid characters within.
-fn write_percent_escaped<B, F>(buf: &mut B, path: &[u8], is_valid: F) -> Result<()>
+pub(crate) fn write_percent_escaped<B, F>(buf: &mut B, path: &[u8], is_valid: F) -> Result<()>
 where
     B: BufMut,
     F: Fn(u8) -> bool,
@@ -203,7 +371,7 @@ where
             percent_encode(buf, byte)?;
             idx + 1
         } else {
-            try_write(buf, &bytes)?;
+            try_write(buf, bytes)?;
             bytes.len()
         };
 
@@ -213,13 +381,88 @@ where
     Ok(())
 }
 
+// Whether `byte` may appear unescaped within a form-urlencoded name or
+// value, as defined by the WHATWG URL standard.
+fn is_form_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'*' | b'-' | b'.' | b'_')
+}
+
+// Write out a form-urlencoded name or value. Spaces are written as `+`
+// and everything else outside of the allowed set is percent-encoded.
+pub(crate) fn write_form_escaped<B: BufMut>(buf: &mut B, value: &[u8]) -> Result<()> {
+    for (i, part) in value.split(|&c| c == b' ').enumerate() {
+        if i != 0 {
+            try_write(buf, b"+")?;
+        }
+
+        write_percent_escaped(buf, part, is_form_char)?;
+    }
+
+    Ok(())
+}
+
+// Write out a sequence of name-value pairs as an
+// `application/x-www-form-urlencoded` body.
+pub(crate) fn write_form<B, I, K, V>(buf: &mut B, pairs: I) -> Result<()>
+where
+    B: BufMut,
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    for (i, (key, val)) in pairs.into_iter().enumerate() {
+        if i != 0 {
+            try_write(buf, b"&")?;
+        }
+
+        write_form_escaped(buf, key.as_ref())?;
+        try_write(buf, b"=")?;
+        write_form_escaped(buf, val.as_ref())?;
+    }
+
+    Ok(())
+}
+
+// Copy the entire contents of `src` into the buffer. The caller is
+// responsible for checking that there is enough space.
+pub(crate) fn put_buf<B: BufMut, I: Buf>(buf: &mut B, src: &mut I) {
+    while src.has_remaining() {
+        let bytes = src.bytes();
+        buf.put_slice(bytes);
+
+        let len = bytes.len();
+        src.advance(len);
+    }
+}
+
+// Number of digits needed to write out `num` in hexadecimal.
+pub(crate) fn hex_len(num: usize) -> usize {
+    let bits = (core::mem::size_of::<usize>() * 8) as u32 - num.leading_zeros();
+
+    (bits.div_ceil(4) as usize).max(1)
+}
+
+// Write out a usize in hexadecimal to the buffer. The caller is
+// responsible for checking that there is enough space.
+pub(crate) fn write_hex<B: BufMut>(buf: &mut B, num: usize) {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    for i in (0..hex_len(num)).rev() {
+        buf.put_u8(DIGITS[(num >> (i * 4)) & 0xF]);
+    }
+}
+
 pub(crate) fn write_version<B: BufMut>(buf: &mut B, version: Version) -> Result<()> {
+    try_write(buf, version_bytes(version)?)
+}
+
+pub(crate) fn version_bytes(version: Version<'_>) -> Result<&[u8]> {
     match version {
-        Version::Http10 => try_write(buf, HTTP_1_0),
-        Version::Http11 => try_write(buf, HTTP_1_1),
+        Version::Http10 => Ok(HTTP_1_0),
+        Version::Http11 => Ok(HTTP_1_1),
         Version::Custom(version) => {
             if validate_version(version) {
-                try_write(buf, version.as_bytes())
+                Ok(version.as_bytes())
             } else {
                 Err(Error::InvalidVersion)
             }
@@ -253,26 +496,23 @@ pub(crate) fn write_request_line<B: BufMut>(
     Ok(())
 }
 
-// Write out a u16 in decimal to the buffer.
-pub(crate) fn write_u16<B: BufMut>(buf: &mut B, mut num: u16) -> Result<()> {
-    let mut bytes = [0u8; 5];
-    let mut idx = 0;
-
-    while num != 0 {
-        bytes[idx] = (num % 10) as u8;
-        num /= 10;
-        idx += 1;
-    }
-
-    if idx > buf.remaining_mut() {
-        return Err(Error::OutOfBuffer);
-    }
-
-    for i in (0..idx).rev() {
-        buf.put_u8(b'0' + bytes[i]);
-    }
+// An upper bound on the length of a request line, assuming that every
+// byte of the URI is percent-encoded. Unlike measuring the request line
+// this doesn't escape the URI. Every part of the request line is
+// validated so writing it out can then only fail if there isn't enough
+// space.
+pub(crate) fn request_line_max_len(method: Method, uri: Uri, version: Version) -> Result<usize> {
+    let uri = match uri.data {
+        UriData::Unescaped([]) => return Err(Error::InvalidUri),
+        UriData::Unescaped(path) => path.len() * 3,
+        UriData::Escaped(path) => path.len(),
+        UriData::Parts(parts) if !crate::uri::validate_parts(&parts) => {
+            return Err(Error::InvalidUri)
+        }
+        UriData::Parts(parts) => crate::uri::max_len(&parts),
+    };
 
-    Ok(())
+    Ok(method_bytes(method)?.len() + uri + version_bytes(version)?.len() + b"  \r\n".len())
 }
 
 // Write out a status code + reason phrase as specified in RFC 7370.
@@ -296,8 +536,13 @@ pub(crate) fn write_u16<B: BufMut>(buf: &mut B, mut num: u16) -> Result<()> {
 // > ignore the reason-phrase content.
 // >
 // >     reason-phrase  = *( HTAB / SP / VCHAR / obs-text )
+//
+// Codes below 100 are zero-padded out to three digits.
 pub(crate) fn write_status<B: BufMut>(buf: &mut B, status: Status) -> Result<()> {
-    write_u16(buf, status.code)
+    let digit = |num: u16| b'0' + (num % 10) as u8;
+    let code = status.code;
+
+    try_write(buf, &[digit(code / 100), digit(code / 10), digit(code)])
 }
 
 // > 3.1.2.  Status Line
@@ -334,6 +579,10 @@ pub(crate) fn write_status_line<B: BufMut>(
     status: Status,
     reason: &str,
 ) -> Result<()> {
+    if !validate_reason(reason.as_bytes()) {
+        return Err(Error::InvalidReason);
+    }
+
     write_version(buf, version)?;
     try_write(buf, b" ")?;
     write_status(buf, status)?;
@@ -344,21 +593,41 @@ pub(crate) fn write_status_line<B: BufMut>(
     Ok(())
 }
 
-// We're already outside of the HTTP standard here so
-// this currently just checks that the method doesn't
-// contain a space or a newline.
+// The length of a status line, whose status code is always three digits.
+// The version and reason phrase are validated so writing the status line
+// out can then only fail if there isn't enough space.
+pub(crate) fn status_line_len(version: Version, reason: &str) -> Result<usize> {
+    if !validate_reason(reason.as_bytes()) {
+        return Err(Error::InvalidReason);
+    }
+
+    Ok(version_bytes(version)?.len() + b" 000 ".len() + reason.len() + b"\r\n".len())
+}
+
+// Custom methods are outside of the HTTP standard so this only checks
+// that the method is non-empty and doesn't contain a space or any
+// control characters, which would break up the request line.
 pub(crate) fn validate_method(method: &str) -> bool {
-    !method
-        .as_bytes()
-        .iter()
-        .copied()
-        .any(|c| c == b' ' || c == b'\r' || c == b'\n')
+    validate_custom(method.as_bytes())
 }
 
-// We're already outside the HTTP standard here so just
-// check to see that the version doesn't contain a space.
+// As with methods, custom versions are outside of the HTTP standard so
+// this only checks that the version won't break up the start line.
 pub(crate) fn validate_version(version: &str) -> bool {
-    !version.as_bytes().iter().copied().any(|c| c == b' ')
+    validate_custom(version.as_bytes())
+}
+
+fn validate_custom(part: &[u8]) -> bool {
+    !part.is_empty() && !part.iter().any(|&c| c == b' ' || c.is_ascii_control())
+}
+
+// Validate a reason phrase as defined by RFC7230.
+//
+// >     reason-phrase  = *( HTAB / SP / VCHAR / obs-text )
+pub(crate) fn validate_reason(reason: &[u8]) -> bool {
+    reason
+        .iter()
+        .all(|&c| c == b'\t' || c == b' ' || (c > b' ' && c != 0x7F))
 }
 
 // Validate a header name as defined by RFC7230. This implementation
@@ -391,15 +660,91 @@ pub(crate) fn validate_version(version: &str) -> bool {
 // >                    | "/" | "[" | "]" | "?" | "="
 // >                    | "{" | "}" | SP | HT
 pub(crate) fn validate_header_name(header: &[u8]) -> bool {
-    fn is_valid(byte: u8) -> bool {
-        // Bit-packed lookup table of all characters that
-        // are valid within `token`.
-        const LOOKUP: u128 = 0x57FFFFFFC7FFFFFE03FF6CFE00000000;
+    validate_token(header)
+}
+
+// Whether `byte` is a `tchar` as defined by RFC7230.
+//
+// >     tchar          = "!" / "#" / "$" / "%" / "&" / "'" / "*"
+// >                    / "+" / "-" / "." / "^" / "_" / "`" / "|" / "~"
+// >                    / DIGIT / ALPHA
+pub(crate) fn is_tchar(byte: u8) -> bool {
+    // Bit-packed lookup table of all characters that
+    // are valid within `token`.
+    const LOOKUP: u128 = 0x57FFFFFFC7FFFFFE03FF6CFA00000000;
+
+    (byte < 128) & ((LOOKUP.wrapping_shr(byte as u32) & 1) != 0)
+}
+
+// Validate a token as defined by RFC7230.
+//
+// >     token          = 1*tchar
+pub(crate) fn validate_token(token: &[u8]) -> bool {
+    !token.is_empty() && token.iter().copied().all(is_tchar)
+}
 
-        (byte < 128) & ((LOOKUP.wrapping_shr(byte as u32) & 1) != 0)
+// Validate the contents of an opaque-tag as defined by RFC7232.
+//
+// >     opaque-tag     = DQUOTE *etagc DQUOTE
+// >     etagc          = %x21 / %x23-7E / obs-text
+// >                    ; VCHAR except double quotes, plus obs-text
+pub(crate) fn validate_etagc(tag: &[u8]) -> bool {
+    tag.iter().all(|&c| c == 0x21 || (c >= 0x23 && c != 0x7F))
+}
+
+// Calculate the length of `value` once written out as a quoted-string,
+// as defined by RFC7230. This implementation does not allow for any
+// obsolete syntax.
+//
+// Returns `None` if `value` contains a character that cannot be
+// represented within a quoted-string.
+//
+// >     quoted-string  = DQUOTE *( qdtext / quoted-pair ) DQUOTE
+// >     qdtext         = HTAB / SP /%x21 / %x23-5B / %x5D-7E / obs-text
+// >     quoted-pair    = "\" ( HTAB / SP / VCHAR / obs-text )
+pub(crate) fn quoted_string_len(value: &[u8]) -> Option<usize> {
+    let mut len = b"\"\"".len();
+
+    for &c in value {
+        len += match c {
+            b'"' | b'\\' => 2,
+            b'\t' | b' ' => 1,
+            c if c.is_ascii_graphic() => 1,
+            _ => return None,
+        };
     }
 
-    header.iter().copied().all(is_valid)
+    Some(len)
+}
+
+// Write out `value` as a quoted-string. The caller is responsible for
+// validating `value` with `quoted_string_len` and checking that there
+// is enough space.
+pub(crate) fn write_quoted_string<B: BufMut>(buf: &mut B, value: &[u8]) {
+    buf.put_u8(b'"');
+
+    for &c in value {
+        if c == b'"' || c == b'\\' {
+            buf.put_u8(b'\\');
+        }
+
+        buf.put_u8(c);
+    }
+
+    buf.put_u8(b'"');
+}
+
+// Write out `value` as a quoted-string, failing if it can't be
+// represented as one or if there isn't enough space.
+pub(crate) fn write_quoted<B: BufMut>(buf: &mut B, value: &[u8]) -> Result<()> {
+    match quoted_string_len(value) {
+        Some(len) if buf.remaining_mut() >= len => {
+            write_quoted_string(buf, value);
+            Ok(())
+        }
+        Some(_) => Err(Error::OutOfBuffer),
+        None => Err(Error::InvalidHeaderValue),
+    }
 }
 
 // Validate a header value as defined by RFC7230. This implementation
@@ -479,7 +824,7 @@ pub(crate) fn lookup_status_line(status: Status) -> Option<&'static str> {
         409 => "Conflict",
         410 => "Gone",
         411 => "Length Required",
-        412 => "Precondition Required",
+        412 => "Precondition Failed",
         413 => "Request Entity Too Large",
         414 => "Request-URI Too Large",
         415 => "Unsupported Media Type",
@@ -511,7 +856,7 @@ pub(crate) fn lookup_status_line(status: Status) -> Option<&'static str> {
         508 => "Loop Detected",
         // 509 Unassigned
         510 => "Not Extended",
-        509 => "Network Authentication Required",
+        511 => "Network Authentication Required",
 
         _ => return None,
     })
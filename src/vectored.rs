@@ -0,0 +1,186 @@
+use std::io::{self, IoSlice, Write};
+use std::ops::Range;
+
+use crate::util::{
+    lookup_status_line, validate_header_field, validate_header_name, write_request_line,
+    write_status_line,
+};
+use crate::{Error, HeaderValue, HttpBuilder, Method, Result, Status, Uri, Version};
+
+/// Builder for HTTP messages that borrows large header values and bodies
+/// instead of copying them.
+///
+/// Small pieces such as the start line, separators and numbers are
+/// written into an owned scratch buffer. The completed [`VectoredMessage`]
+/// is a list of slices suitable for `write_vectored`.
+#[derive(Debug)]
+pub struct VectoredBuilder<'a> {
+    scratch: Vec<u8>,
+    segments: Vec<Segment<'a>>,
+    // Start of the scratch bytes that are not yet part of a segment.
+    start: usize,
+}
+
+/// A message built by a [`VectoredBuilder`].
+#[derive(Debug)]
+pub struct VectoredMessage<'a> {
+    scratch: Vec<u8>,
+    segments: Vec<Segment<'a>>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment<'a> {
+    Scratch(Range<usize>),
+    Borrowed(&'a [u8]),
+}
+
+impl<'a> VectoredBuilder<'a> {
+    /// Create a new request with the provided header line.
+    pub fn request(method: Method, version: Version, uri: Uri) -> Result<Self> {
+        let mut scratch = Vec::new();
+        write_request_line(&mut scratch, method, uri, version)?;
+
+        Ok(Self::new(scratch))
+    }
+
+    /// Create a new response from the provided status line.
+    pub fn response(version: Version, status: Status) -> Result<Self> {
+        Self::response_with_reason(version, status, lookup_status_line(status).unwrap_or(" "))
+    }
+
+    /// Create a new response from the provided status line.
+    pub fn response_with_reason(version: Version, status: Status, reason: &str) -> Result<Self> {
+        let mut scratch = Vec::new();
+        write_status_line(&mut scratch, version, status, reason)?;
+
+        Ok(Self::new(scratch))
+    }
+
+    fn new(scratch: Vec<u8>) -> Self {
+        Self {
+            scratch,
+            segments: Vec::new(),
+            start: 0,
+        }
+    }
+
+    /// Add a new header, copying the value into the scratch buffer.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn header(&mut self, key: impl AsRef<[u8]>, val: impl HeaderValue) -> Result<&mut Self> {
+        // Safety: The scratch buffer always ends at the end of a line
+        //         within the header section.
+        let mut builder = unsafe { HttpBuilder::from_buf(&mut self.scratch) };
+        builder.header(key, val)?;
+
+        Ok(self)
+    }
+
+    /// Add a new header whose value is borrowed rather than copied.
+    ///
+    /// # Note
+    /// This method is atomic - if it fails then nothing will be written
+    /// to the buffer.
+    pub fn header_ref(&mut self, key: impl AsRef<[u8]>, val: &'a [u8]) -> Result<&mut Self> {
+        let key = key.as_ref();
+
+        if !validate_header_name(key) {
+            return Err(Error::InvalidHeaderKey);
+        }
+        if !validate_header_field(val) {
+            return Err(Error::InvalidHeaderValue);
+        }
+
+        self.scratch.extend_from_slice(key);
+        self.scratch.extend_from_slice(b": ");
+        self.borrow(val);
+        self.scratch.extend_from_slice(b"\r\n");
+
+        Ok(self)
+    }
+
+    /// Complete the HTTP header.
+    pub fn finish(mut self) -> VectoredMessage<'a> {
+        self.scratch.extend_from_slice(b"\r\n");
+        self.into_message()
+    }
+
+    /// Complete the HTTP header followed by a borrowed body.
+    pub fn body(mut self, body: &'a [u8]) -> VectoredMessage<'a> {
+        self.scratch.extend_from_slice(b"\r\n");
+        self.borrow(body);
+        self.into_message()
+    }
+
+    fn borrow(&mut self, bytes: &'a [u8]) {
+        self.push_scratch();
+        if !bytes.is_empty() {
+            self.segments.push(Segment::Borrowed(bytes));
+        }
+    }
+
+    // Turn any scratch bytes written since the last segment into a new
+    // segment.
+    fn push_scratch(&mut self) {
+        let end = self.scratch.len();
+
+        if end > self.start {
+            self.segments.push(Segment::Scratch(self.start..end));
+            self.start = end;
+        }
+    }
+
+    fn into_message(mut self) -> VectoredMessage<'a> {
+        self.push_scratch();
+
+        VectoredMessage {
+            scratch: self.scratch,
+            segments: self.segments,
+        }
+    }
+}
+
+impl VectoredMessage<'_> {
+    /// The slices making up the message, in order.
+    pub fn slices(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.segments.iter().map(move |segment| match segment {
+            Segment::Scratch(range) => &self.scratch[range.clone()],
+            Segment::Borrowed(bytes) => *bytes,
+        })
+    }
+
+    /// The slices making up the message as `IoSlice`s.
+    pub fn io_slices(&self) -> Vec<IoSlice<'_>> {
+        self.slices().map(IoSlice::new).collect()
+    }
+
+    /// The total length of the message.
+    pub fn len(&self) -> usize {
+        self.slices().map(<[u8]>::len).sum()
+    }
+
+    /// Whether the message is empty. This is never the case for a
+    /// completed message.
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Write the whole message out using `write_vectored`.
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let mut slices = self.io_slices();
+        let mut slices = &mut slices[..];
+
+        while !slices.is_empty() {
+            match writer.write_vectored(slices) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}